@@ -0,0 +1,158 @@
+//! AOCV derate tables from the LVF moments, for flows without LVF or POCV.
+//!
+//! A path of `depth` identical stages with independent variation has mean
+//! `depth * (nominal + mean_shift)` and std dev `sqrt(depth) * std_dev`, so the
+//! late derate at `n_sigma` is `1 + shift / nominal + n_sigma * std_dev / (nominal * sqrt(depth))`
+//! and the early derate mirrors it. Each cell gets, per rise/fall, the most
+//! pessimistic derate over the delay table points of its arcs.
+use crate::arcs::{lvf_tables, ArcInfo};
+use liberty_db::{DefaultCtx, Library};
+use std::{collections::BTreeMap, fmt, io::Write};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DerateType {
+  Early,
+  Late,
+}
+
+impl fmt::Display for DerateType {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(match self {
+      DerateType::Early => "early",
+      DerateType::Late => "late",
+    })
+  }
+}
+
+pub const DEPTHS: [usize; 8] = [1, 2, 3, 4, 5, 6, 8, 10];
+
+/// Derates of one cell and edge at each depth
+#[derive(Debug, Clone, PartialEq)]
+pub struct Derate {
+  pub cell: String,
+  pub rise_fall: &'static str,
+  pub derate_type: DerateType,
+  pub derates: Vec<f64>,
+}
+
+/// Derate of one table point at `depth`
+pub fn derate(
+  derate_type: DerateType,
+  shift_ratio: f64,
+  sigma_ratio: f64,
+  n_sigma: f64,
+  depth: usize,
+) -> f64 {
+  let spread = n_sigma * sigma_ratio / (depth as f64).sqrt();
+  match derate_type {
+    DerateType::Early => 1.0 + shift_ratio - spread,
+    DerateType::Late => 1.0 + shift_ratio + spread,
+  }
+}
+
+/// Early and late derates of every cell and edge of `infos`, in name order
+pub fn derates(
+  lib: &Library<DefaultCtx>,
+  infos: &[ArcInfo],
+  depths: &[usize],
+  n_sigma: f64,
+) -> anyhow::Result<Vec<Derate>> {
+  let mut ratios: BTreeMap<(&str, &'static str), Vec<(f64, f64)>> = BTreeMap::new();
+  for info in infos {
+    let [(_, table), _] = lvf_tables(*info, lib)?;
+    let rise_fall = if info.6 { "rise" } else { "fall" };
+    ratios.entry((info.1, rise_fall)).or_default().extend(
+      table
+        .values
+        .iter()
+        .zip(table.lvf_values.iter())
+        .filter(|(nominal, _)| **nominal > 0.0)
+        .map(|(nominal, lvf)| ((lvf.mean - nominal) / nominal, lvf.std_dev / nominal)),
+    );
+  }
+  let mut derates = Vec::new();
+  for derate_type in [DerateType::Early, DerateType::Late] {
+    for ((cell, rise_fall), points) in ratios.iter() {
+      let worst = |depth: usize| {
+        let at = points
+          .iter()
+          .map(|(shift, sigma)| derate(derate_type, *shift, *sigma, n_sigma, depth));
+        match derate_type {
+          DerateType::Early => at.fold(f64::INFINITY, f64::min),
+          DerateType::Late => at.fold(f64::NEG_INFINITY, f64::max),
+        }
+      };
+      derates.push(Derate {
+        cell: cell.to_string(),
+        rise_fall,
+        derate_type,
+        derates: depths.iter().map(|depth| worst(*depth)).collect(),
+      });
+    }
+  }
+  Ok(derates)
+}
+
+/// Writes the tables of `derate_type` as an AOCV file
+pub fn write_aocv(
+  writer: &mut impl Write,
+  lib_name: &str,
+  derate_type: DerateType,
+  depths: &[usize],
+  derates: &[Derate],
+) -> std::io::Result<()> {
+  writeln!(writer, "version: 1.0")?;
+  let depth: Vec<String> = depths.iter().map(usize::to_string).collect();
+  for d in derates.iter().filter(|d| d.derate_type == derate_type) {
+    let table: Vec<String> = d.derates.iter().map(|v| format!("{v:.4}")).collect();
+    writeln!(writer)?;
+    writeln!(writer, "object_type: lib_cell")?;
+    writeln!(writer, "rf_type: {}", d.rise_fall)?;
+    writeln!(writer, "delay_type: cell")?;
+    writeln!(writer, "derate_type: {derate_type}")?;
+    writeln!(writer, "object_spec: {lib_name}/{}", d.cell)?;
+    writeln!(writer, "depth: {}", depth.join(" "))?;
+    writeln!(writer, "table: {}", table.join(" "))?;
+  }
+  Ok(())
+}
+
+#[test]
+fn aocv_derate() {
+  // 5% shift, 10% sigma at 3 sigma: 1.35 at depth 1, 1.2 at depth 4
+  assert!((derate(DerateType::Late, 0.05, 0.1, 3.0, 1) - 1.35).abs() < 1e-12);
+  assert!((derate(DerateType::Late, 0.05, 0.1, 3.0, 4) - 1.2).abs() < 1e-12);
+  assert!((derate(DerateType::Early, 0.05, 0.1, 3.0, 4) - 0.9).abs() < 1e-12);
+  let d = Derate {
+    cell: "INVD1".into(),
+    rise_fall: "rise",
+    derate_type: DerateType::Late,
+    derates: vec![1.35, 1.2],
+  };
+  let mut out = Vec::new();
+  write_aocv(&mut out, "lib", DerateType::Late, &[1, 4], &[d]).unwrap();
+  assert_eq!(
+    String::from_utf8(out).unwrap(),
+    "version: 1.0\n\nobject_type: lib_cell\nrf_type: rise\ndelay_type: cell\n\
+     derate_type: late\nobject_spec: lib/INVD1\ndepth: 1 4\ntable: 1.3500 1.2000\n"
+  );
+}
+
+#[test]
+fn aocv_files() -> anyhow::Result<()> {
+  use crate::corner::Corner;
+  for corner in Corner::all() {
+    let lib_path = format!("pruned_active_lvf_{corner}.lib");
+    if !std::path::Path::new(&lib_path).exists() {
+      continue;
+    }
+    let lib = crate::read_lib(&lib_path)?;
+    let derates = derates(&lib, &crate::arcs::INFO, &DEPTHS, 3.0)?;
+    for derate_type in [DerateType::Early, DerateType::Late] {
+      let out_path = format!("{corner}_{derate_type}.aocv");
+      let mut writer = std::io::BufWriter::new(std::fs::File::create(out_path)?);
+      write_aocv(&mut writer, &lib.name, derate_type, &DEPTHS, &derates)?;
+    }
+  }
+  Ok(())
+}
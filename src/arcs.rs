@@ -1,21 +1,49 @@
+use crate::{
+  corner::Corner,
+  fill::{fill, GapPolicy},
+  moments::{read_moments, PointMoments},
+  plausibility::{check_table, enforce, PlausibilityOptions, Violation},
+  provenance::{self, hash_files, Provenance, Run},
+  stats::read_samples,
+};
+use anyhow::{bail, Context as _};
 use liberty_db::{
-  common::{items::WordSet, table::TableLookUp},
+  expression::LogicBooleanExpression,
   timing::{
-    items::TimingSenseType::{self, NegativeUnate, PositiveUnate},
-    Timing, TimingType,
+    items::{
+      LVFValue,
+      TimingSenseType::{self, NegativeUnate, PositiveUnate},
+    },
+    Timing, TimingTableLookUp, TimingType,
   },
-  Cell, DefaultCtx, Pin,
+  units::TimeUnit,
+  DefaultCtx, Library,
 };
-use ordered_float::NotNan;
+use serde::Serialize;
+#[cfg(test)]
 use std::{
-  collections::{BinaryHeap, HashMap, HashSet},
-  fs::{self, File},
+  collections::{BTreeMap, HashMap},
+  fs::File,
   io::{BufWriter, Write},
-  path::Path,
-  str::FromStr,
 };
+use std::{
+  fmt,
+  path::{Path, PathBuf},
+};
+
+/// `(cell_group, cell, pin, related_pin, arc_num, when, is_rise, timing_sense)`
+pub type ArcInfo = (
+  &'static str,
+  &'static str,
+  &'static str,
+  &'static str,
+  &'static str,
+  &'static str,
+  bool,
+  TimingSenseType,
+);
 
-const INFO: [(&str, &str, &str, &str, &str, &str, bool, TimingSenseType); 54] = [
+pub const INFO: [ArcInfo; 54] = [
   ("AN2", "AN2D1BWP30P140", "Z", "A2", "001", "A1", true, PositiveUnate),
   ("AN2", "AN2D1BWP30P140", "Z", "A1", "002", "A2", true, PositiveUnate),
   ("AN2", "AN2D1BWP30P140", "Z", "A1", "003", "A2", false, PositiveUnate),
@@ -72,155 +100,446 @@ const INFO: [(&str, &str, &str, &str, &str, &str, bool, TimingSenseType); 54] =
   ("OAI21", "OAI21D1BWP30P140", "ZN", "B", "010", "A1&!A2", true, NegativeUnate),
 ];
 
-struct Arc {
-  nominal: TableLookUp,
-  mean_shift: TableLookUp,
-  std_dev: TableLookUp,
-  skewness: TableLookUp,
+pub(crate) fn push_comment(comments: &mut String, comment: &str) {
+  if !comments.is_empty() {
+    comments.push('\n');
+  }
+  comments.push_str(comment);
+}
+
+fn lvf_table<'a>(
+  table: &'a mut Option<TimingTableLookUp<DefaultCtx>>,
+  name: &str,
+) -> anyhow::Result<&'a mut TimingTableLookUp<DefaultCtx>> {
+  let table = table.as_mut().with_context(|| format!("missing {name} table"))?;
+  if table.lvf_values.len() != table.values.len() {
+    bail!("{name} table has no LVF values");
+  }
+  Ok(table)
+}
+
+/// How btdcell numbers the points of a table
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PointOrder {
+  /// point = i1 * len(index_2) + i2
+  #[default]
+  RowMajor,
+  /// point = i2 * len(index_1) + i1
+  ColumnMajor,
+}
+
+impl PointOrder {
+  /// `(i1, i2)` of `point` in a `size1` x `size2` table, 1-D tables have `size2 == 1`
+  pub fn position(
+    self,
+    point: usize,
+    size1: usize,
+    size2: usize,
+  ) -> Option<(usize, usize)> {
+    if point >= size1 * size2 {
+      return None;
+    }
+    Some(match self {
+      PointOrder::RowMajor => (point / size2, point % size2),
+      PointOrder::ColumnMajor => (point % size1, point / size1),
+    })
+  }
+}
+
+/// `(len(index_1), len(index_2))` of `table`, after checking its values fit them
+fn table_shape(table: &TimingTableLookUp<DefaultCtx>) -> anyhow::Result<(usize, usize)> {
+  let shape = (table.index_1.len().max(1), table.index_2.len().max(1));
+  if table.values.len() != shape.0 * shape.1 {
+    bail!(
+      "{} values in a {}x{} table {}",
+      table.values.len(),
+      shape.0,
+      shape.1,
+      table.name
+    );
+  }
+  Ok(shape)
+}
+
+/// What btdcell left for each point
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PointSource {
+  /// `<point>_moments.csv` with the moments computed by btdcell
+  #[default]
+  Moments,
+  /// `<point>_samples.csv` with the raw samples, see [`crate::stats`]
+  Samples,
+}
+
+impl PointSource {
+  fn file_name(self, point: usize) -> String {
+    match self {
+      PointSource::Moments => format!("{point}_moments.csv"),
+      PointSource::Samples => format!("{point}_samples.csv"),
+    }
+  }
+  /// Inverse of [`Self::file_name`]
+  fn point(self, file_name: &str) -> Option<usize> {
+    let suffix = match self {
+      PointSource::Moments => "_moments.csv",
+      PointSource::Samples => "_samples.csv",
+    };
+    file_name.strip_suffix(suffix)?.parse().ok()
+  }
+  fn read(self, file: &Path) -> anyhow::Result<PointMoments> {
+    match self {
+      PointSource::Moments => Ok(read_moments(file)?),
+      PointSource::Samples => read_samples(file),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CollectOptions {
+  pub order: PointOrder,
+  pub gaps: GapPolicy,
+  pub source: PointSource,
+  /// whose results are read
+  pub corner: Corner,
+  /// recorded in the provenance of the collected tables
+  pub run: Option<Run>,
+  pub plausibility: PlausibilityOptions,
+}
+
+/// Which points of one OCV table got a simulation result
+#[derive(Debug, Clone, Serialize)]
+pub struct Coverage {
+  pub cell: String,
+  pub arc: String,
+  pub table: &'static str,
+  pub points: usize,
+  /// row-major positions without a usable result
+  pub missing: Vec<usize>,
+  pub gaps: GapPolicy,
+  pub provenance: Provenance,
+  pub violations: Vec<Violation>,
+  /// points whose mean is far from the nominal value
+  pub warnings: Vec<String>,
 }
 
-fn update_cell(
-  info: (&str, &str, &str, &str, &str, &str, bool, TimingSenseType),
-  template_lib: &mut liberty_db::Library<DefaultCtx>,
+impl fmt::Display for Coverage {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let covered = self.points - self.missing.len();
+    write!(f, "{} arc{} {}: {covered}/{}", self.cell, self.arc, self.table, self.points)?;
+    if !self.missing.is_empty() {
+      write!(f, ", missing {:?} ({})", self.missing, self.gaps)?;
+    }
+    if !self.violations.is_empty() {
+      write!(f, ", {} implausible", self.violations.len())?;
+      for violation in self.violations.iter() {
+        write!(f, "\n  {violation}")?;
+      }
+    }
+    for warning in self.warnings.iter() {
+      write!(f, "\n  warning: {warning}")?;
+    }
+    Ok(())
+  }
+}
+
+/// Fills the LVF values of the points that are not `known`, the mean through its
+/// shift from the nominal value.
+fn fill_lvf(
+  gaps: GapPolicy,
+  table: &mut TimingTableLookUp<DefaultCtx>,
+  known: &[bool],
 ) -> anyhow::Result<()> {
-  let (cell_group, cell, pin, related_pin, arc_num, when, is_rise, timing_sense) = info;
-  let cell = template_lib.cell.get_mut(cell).expect("msg_cell");
-  let when = if when == "" { None } else { Some(cell.parse_logic_booleanexpr(when)?) };
-  let timing = cell
+  let grid = |value: &dyn Fn(usize) -> f64| -> Vec<Option<f64>> {
+    (0..known.len()).map(|i| known[i].then(|| value(i))).collect()
+  };
+  let mut shift = grid(&|i| table.lvf_values[i].mean - table.values[i]);
+  let mut std_dev = grid(&|i| table.lvf_values[i].std_dev);
+  let mut skewness = grid(&|i| table.lvf_values[i].skewness);
+  for values in [&mut shift, &mut std_dev, &mut skewness] {
+    fill(gaps, &table.index_1, &table.index_2, values)?;
+  }
+  if matches!(gaps, GapPolicy::Bilinear | GapPolicy::Spline) {
+    for i in (0..known.len()).filter(|i| !known[*i]) {
+      table.lvf_values[i] = LVFValue {
+        mean: table.values[i] + shift[i].expect("filled"),
+        std_dev: std_dev[i].expect("filled"),
+        skewness: skewness[i].expect("filled"),
+      };
+    }
+  }
+  Ok(())
+}
+
+/// Collected means further than this factor away from the nominal value hint
+/// that the CSV and the library disagree on the time unit; they end up in
+/// [`Coverage::warnings`].
+const UNIT_MISMATCH_RATIO: f64 = 10.0;
+
+fn check_unit(mean: f64, nominal: f64) -> bool {
+  let ratio = (mean / nominal).abs();
+  nominal == 0.0 || (UNIT_MISMATCH_RATIO.recip() < ratio && ratio < UNIT_MISMATCH_RATIO)
+}
+
+/// `timing_type` of the one timing group of an arc, delay or constraint alike
+pub(crate) fn arc_timing_type<'a>(
+  timings: impl IntoIterator<Item = &'a Timing<DefaultCtx>>,
+  related_pin: &str,
+  timing_sense: TimingSenseType,
+  when: Option<&LogicBooleanExpression>,
+) -> anyhow::Result<Option<TimingType>> {
+  let types: Vec<Option<TimingType>> = timings
+    .into_iter()
+    .filter(|t| {
+      t.related_pin.to_string() == related_pin
+        && t.timing_sense == Some(timing_sense)
+        && t.when.as_ref() == when
+    })
+    .map(|t| t.timing_type)
+    .collect();
+  match types[..] {
+    [timing_type] => Ok(timing_type),
+    [] => bail!("no timing group"),
+    _ => bail!("{} timing groups, of types {types:?}", types.len()),
+  }
+}
+
+pub fn update_cell(
+  info: ArcInfo,
+  options: &CollectOptions,
+  template_lib: &mut Library<DefaultCtx>,
+) -> anyhow::Result<Vec<Coverage>> {
+  let (_, cell_name, pin_name, related_pin, arc_num, when, _, timing_sense) = info;
+  let timing_id = if when.is_empty() {
+    related_pin.to_string()
+  } else {
+    format!("{related_pin} when {when}")
+  };
+  let time_unit = template_lib.time_unit;
+  let cell = template_lib
+    .cell
+    .get_mut(cell_name)
+    .with_context(|| format!("cell {cell_name}"))?;
+  let when = if when.is_empty() { None } else { Some(cell.parse_logic_boolexpr(when)?) };
+  let pin = cell
     .pin
-    .get_mut(pin.into())
-    .expect("msg_pin")
+    .get_mut(pin_name.into())
+    .with_context(|| format!("cell {cell_name} pin {pin_name}"))?;
+  let context = || format!("cell {cell_name} pin {pin_name} arc{arc_num} timing");
+  let timing_type =
+    arc_timing_type(pin.timing.iter(), related_pin, timing_sense, when.as_ref())
+      .with_context(context)?;
+  let timing = pin
     .timing
-    .get_mut(
-      related_pin.into(),
-      Some(&timing_sense),
-      Some(&TimingType::COMBINATIONAL),
-      when.as_ref(),
-    )
-    .expect("msg_timing");
+    .take(related_pin.into(), Some(&timing_sense), timing_type.as_ref(), when.as_ref())
+    .with_context(context)?;
+  // work on a copy so a failed arc leaves the library as it was
+  let mut updated = timing.clone();
+  let result = collect_timing(info, options, time_unit, &mut updated, timing_id);
+  pin.timing.insert(if result.is_ok() { updated } else { timing });
+  result
+}
 
-  let (mut delay_arc, mut transition_arc) = if is_rise {
-    (
-      Arc {
-        nominal: timing.cell_rise.clone().expect("msg_table"),
-        mean_shift: timing.ocv_mean_shift_cell_rise.clone().expect("msg_table"),
-        std_dev: timing.ocv_std_dev_cell_rise.clone().expect("msg_table"),
-        skewness: timing.ocv_skewness_cell_rise.clone().expect("msg_table"),
-      },
-      Arc {
-        nominal: timing.rise_transition.clone().expect("msg_table"),
-        mean_shift: timing.ocv_mean_shift_rise_transition.clone().expect("msg_table"),
-        std_dev: timing.ocv_std_dev_rise_transition.clone().expect("msg_table"),
-        skewness: timing.ocv_skewness_rise_transition.clone().expect("msg_table"),
-      },
-    )
-  } else {
-    (
-      Arc {
-        nominal: timing.cell_fall.clone().expect("msg_table"),
-        mean_shift: timing.ocv_mean_shift_cell_fall.clone().expect("msg_table"),
-        std_dev: timing.ocv_std_dev_cell_fall.clone().expect("msg_table"),
-        skewness: timing.ocv_skewness_cell_fall.clone().expect("msg_table"),
-      },
-      Arc {
-        nominal: timing.fall_transition.clone().expect("msg_table"),
-        mean_shift: timing.ocv_mean_shift_fall_transition.clone().expect("msg_table"),
-        std_dev: timing.ocv_std_dev_fall_transition.clone().expect("msg_table"),
-        skewness: timing.ocv_skewness_fall_transition.clone().expect("msg_table"),
-      },
-    )
+/// The LVF values of arc `info` in `timing`, read from the results of `options`
+fn collect_timing(
+  info: ArcInfo,
+  options: &CollectOptions,
+  time_unit: TimeUnit,
+  timing: &mut Timing<DefaultCtx>,
+  timing_id: String,
+) -> anyhow::Result<Vec<Coverage>> {
+  let (cell_group, cell_name, pin_name, _, arc_num, _, is_rise, _) = info;
+  // constraint arcs have one table, filled from the delay moments
+  let constraint = timing.cell_rise.is_none()
+    && timing.cell_fall.is_none()
+    && (timing.rise_constraint.is_some() || timing.fall_constraint.is_some());
+  let mut tables = match (constraint, is_rise) {
+    (false, true) => vec![
+      ("cell_rise", lvf_table(&mut timing.cell_rise, "cell_rise")?),
+      ("rise_transition", lvf_table(&mut timing.rise_transition, "rise_transition")?),
+    ],
+    (false, false) => vec![
+      ("cell_fall", lvf_table(&mut timing.cell_fall, "cell_fall")?),
+      ("fall_transition", lvf_table(&mut timing.fall_transition, "fall_transition")?),
+    ],
+    (true, true) => {
+      vec![(
+        "rise_constraint",
+        lvf_table(&mut timing.rise_constraint, "rise_constraint")?,
+      )]
+    }
+    (true, false) => {
+      vec![(
+        "fall_constraint",
+        lvf_table(&mut timing.fall_constraint, "fall_constraint")?,
+      )]
+    }
   };
-  timing.comments._self.push(format!("{cell} {arc_num}").into());
-  for index in 0..64 {
-    let csv_file = format!("/code/ActiveLVF/char/{cell_group}/tt0p8v25c/{cell}/arc{arc_num}/{index}_moments.csv");
-    if Path::new(&csv_file).exists() {
-      let s = std::fs::read_to_string(Path::new(&csv_file)).expect("msg1");
-      let mut s = s.split("\n");
-      s.next();
-      let v: Vec<f64> = s
-        .next()
-        .expect("msg2")
-        .split(",")
-        .map(|s| f64::from_str(s).expect("msg3"))
-        .collect();
-      let (
-        delay_mean,
-        delay_std_dev,
-        delay_skewness,
-        transition_mean,
-        transition_std_dev,
-        transition_skewness,
-      ) = (v[0] * 1e9, v[1] * 1e9, v[2] * 1e9, v[3] * 1e9, v[4] * 1e9, v[5] * 1e9);
-      delay_arc.mean_shift.values.inner[index] =
-        NotNan::new(delay_mean).unwrap() - delay_arc.nominal.values.inner[index];
-      delay_arc.std_dev.values.inner[index] = NotNan::new(delay_std_dev).unwrap();
-      delay_arc.skewness.values.inner[index] = NotNan::new(delay_skewness).unwrap();
-      transition_arc.mean_shift.values.inner[index] = NotNan::new(transition_mean)
-        .unwrap()
-        - transition_arc.nominal.values.inner[index];
-      transition_arc.std_dev.values.inner[index] =
-        NotNan::new(transition_std_dev).unwrap();
-      transition_arc.skewness.values.inner[index] =
-        NotNan::new(transition_skewness).unwrap();
-    } else {
-      println!("{cell_group} {cell} {pin} {related_pin} {arc_num} {index}");
+  let (size1, size2) = table_shape(tables[0].1)?;
+  for (name, table) in tables.iter().skip(1) {
+    if table_shape(table)? != (size1, size2) {
+      bail!(
+        "cell {cell_name} arc{arc_num}: {name} differs in shape from {}",
+        tables[0].0
+      );
     }
   }
-  delay_arc
-    .mean_shift
-    .comments
-    ._self
-    .push(format!("{cell} {arc_num}").into());
-  delay_arc
-    .std_dev
-    .comments
-    ._self
-    .push(format!("{cell} {arc_num}").into());
-  delay_arc
-    .skewness
-    .comments
-    ._self
-    .push(format!("{cell} {arc_num}").into());
-  transition_arc
-    .mean_shift
-    .comments
-    ._self
-    .push(format!("{cell} {arc_num}").into());
-  transition_arc
-    .std_dev
-    .comments
-    ._self
-    .push(format!("{cell} {arc_num}").into());
-  transition_arc
-    .skewness
-    .comments
-    ._self
-    .push(format!("{cell} {arc_num}").into());
-  if is_rise {
-    timing.ocv_mean_shift_cell_rise = Some(delay_arc.mean_shift);
-    timing.ocv_std_dev_cell_rise = Some(delay_arc.std_dev);
-    timing.ocv_skewness_cell_rise = Some(delay_arc.skewness);
-    timing.ocv_mean_shift_rise_transition = Some(transition_arc.mean_shift);
-    timing.ocv_std_dev_rise_transition = Some(transition_arc.std_dev);
-    timing.ocv_skewness_rise_transition = Some(transition_arc.skewness);
-  } else {
-    timing.ocv_mean_shift_cell_fall = Some(delay_arc.mean_shift);
-    timing.ocv_std_dev_cell_fall = Some(delay_arc.std_dev);
-    timing.ocv_skewness_cell_fall = Some(delay_arc.skewness);
-    timing.ocv_mean_shift_fall_transition = Some(transition_arc.mean_shift);
-    timing.ocv_std_dev_fall_transition = Some(transition_arc.std_dev);
-    timing.ocv_skewness_fall_transition = Some(transition_arc.skewness);
+  let arc_dir = options
+    .corner
+    .moments_dir(cell_group)
+    .join(cell_name)
+    .join(format!("arc{arc_num}"));
+  if arc_dir.is_dir() {
+    for entry in std::fs::read_dir(&arc_dir)? {
+      let file = entry?.file_name();
+      let Some(point) = options.source.point(&file.to_string_lossy()) else {
+        continue;
+      };
+      if point >= size1 * size2 {
+        bail!(
+          "{} is beyond the {size1}x{size2} table of cell {cell_name} arc{arc_num}",
+          arc_dir.join(file).display()
+        );
+      }
+    }
   }
-  Ok(())
+  let mut known = vec![false; size1 * size2];
+  let mut warnings = vec![Vec::new(); tables.len()];
+  let mut read = Vec::new();
+  for point in 0..size1 * size2 {
+    let csv_file = arc_dir.join(options.source.file_name(point));
+    let (i1, i2) = options
+      .order
+      .position(point, size1, size2)
+      .expect("point inside the table");
+    let index = i1 * size2 + i2;
+    if !csv_file.exists() {
+      continue;
+    }
+    let measured = options.source.read(&csv_file)?;
+    read.push(csv_file.clone());
+    let moments = [&measured.delay, &measured.transition];
+    if !moments.iter().take(tables.len()).all(|m| m.is_complete()) {
+      continue;
+    }
+    known[index] = true;
+    for (((_, table), moments), warnings) in
+      tables.iter_mut().zip(moments).zip(warnings.iter_mut())
+    {
+      let mean = moments.mean / time_unit.value();
+      if !check_unit(mean, table.values[index]) {
+        warnings.push(format!(
+          "{}: mean {mean} is far from nominal {} in time_unit {time_unit}",
+          csv_file.display(),
+          table.values[index]
+        ));
+      }
+      table.lvf_values[index] = LVFValue {
+        mean,
+        std_dev: moments.std_dev / time_unit.value(),
+        skewness: moments.skewness,
+      };
+    }
+  }
+  let missing: Vec<usize> = (0..known.len()).filter(|i| !known[*i]).collect();
+  let provenance = Provenance {
+    cell: cell_name.to_string(),
+    pin: pin_name.to_string(),
+    timing: timing_id,
+    table: String::new(),
+    arc: arc_num.to_string(),
+    run: options.run.clone(),
+    collected: provenance::now(),
+    sources: hash_files(read.iter().map(PathBuf::as_path))?,
+    predicted_from: Vec::new(),
+  };
+  let mut coverage = Vec::with_capacity(tables.len());
+  for ((name, table), warnings) in tables.into_iter().zip(warnings) {
+    let provenance = Provenance { table: name.to_string(), ..provenance.clone() };
+    push_comment(&mut table.comments, &provenance.comment());
+    if !missing.is_empty() {
+      fill_lvf(options.gaps, table, &known)
+        .with_context(|| format!("cell {cell_name} arc{arc_num} {name}"))?;
+      push_comment(
+        &mut table.comments,
+        &format!("{} of {} points filled: {}", missing.len(), known.len(), options.gaps),
+      );
+    }
+    // points kept from the template are not ours to judge
+    let filled = matches!(options.gaps, GapPolicy::Bilinear | GapPolicy::Spline);
+    let violations: Vec<Violation> = check_table(table, &options.plausibility)
+      .into_iter()
+      .filter(|v| filled || known[v.index.0 * size2 + v.index.1])
+      .collect();
+    enforce(
+      &format!("cell {cell_name} arc{arc_num} {name}"),
+      &violations,
+      &options.plausibility,
+    )?;
+    coverage.push(Coverage {
+      cell: cell_name.to_string(),
+      arc: arc_num.to_string(),
+      table: name,
+      points: known.len(),
+      missing: missing.clone(),
+      gaps: options.gaps,
+      provenance,
+      violations,
+      warnings,
+    });
+  }
+  push_comment(timing.comments_this_entry().or_default(), &provenance.comment());
+  Ok(coverage)
+}
+
+/// `(name, table)` of the delay and the transition table of the arc, checked to
+/// carry LVF values
+pub fn lvf_tables<'a>(
+  info: ArcInfo,
+  lib: &'a Library<DefaultCtx>,
+) -> anyhow::Result<[(&'static str, &'a TimingTableLookUp<DefaultCtx>); 2]> {
+  let (_, cell_name, pin_name, related_pin, arc_num, when, is_rise, timing_sense) = info;
+  let cell = lib.cell.get(cell_name).with_context(|| format!("cell {cell_name}"))?;
+  let when = if when.is_empty() { None } else { Some(cell.parse_logic_boolexpr(when)?) };
+  let pin = cell
+    .pin
+    .get(pin_name.into())
+    .with_context(|| format!("cell {cell_name} pin {pin_name}"))?;
+  let context = || format!("cell {cell_name} pin {pin_name} arc{arc_num} timing");
+  let timing_type =
+    arc_timing_type(pin.timing.iter(), related_pin, timing_sense, when.as_ref())
+      .with_context(context)?;
+  let timing = pin
+    .timing
+    .get(related_pin.into(), Some(&timing_sense), timing_type.as_ref(), when.as_ref())
+    .with_context(context)?;
+  let tables = if is_rise {
+    [("cell_rise", &timing.cell_rise), ("rise_transition", &timing.rise_transition)]
+  } else {
+    [("cell_fall", &timing.cell_fall), ("fall_transition", &timing.fall_transition)]
+  };
+  let checked = |(name, table): (&'static str, &'a Option<_>)| {
+    let table: &TimingTableLookUp<DefaultCtx> = table
+      .as_ref()
+      .with_context(|| format!("cell {cell_name} arc{arc_num}: missing {name} table"))?;
+    if table.lvf_values.len() != table.values.len() {
+      bail!("cell {cell_name} arc{arc_num}: {name} table has no LVF values");
+    }
+    Ok((name, table))
+  };
+  let [delay, transition] = tables;
+  Ok([checked(delay)?, checked(transition)?])
+}
+
+#[cfg(test)]
+fn config_run(cell_group: &str) -> anyhow::Result<Run> {
+  let corner = Corner::default();
+  provenance::read_run(corner.run, corner.config_path(cell_group))
 }
 
 #[test]
 fn collect_by_cell() -> anyhow::Result<()> {
   let template_file = "pruned_100kMC.lib";
-  let mut map: HashMap<
-    &str,
-    Vec<&(&str, &str, &str, &str, &str, &str, bool, TimingSenseType)>,
-  > = HashMap::new();
+  let mut map: HashMap<&str, Vec<&ArcInfo>> = HashMap::new();
   for info in INFO.iter() {
     let cell = info.1;
     match map.get_mut(cell) {
@@ -232,175 +551,173 @@ fn collect_by_cell() -> anyhow::Result<()> {
       }
     }
   }
-  for (cell_name, info_list) in map.iter() {
-    match liberty_db::library::Library::<DefaultCtx>::parse_lib(&std::fs::read_to_string(
-      Path::new(template_file),
-    )?) {
-      Ok(mut template_lib) => {
-        for info in info_list {
-          update_cell(**info, &mut template_lib)?;
+  let template_lib = crate::read_lib(template_file)?;
+  let cells: Vec<(&str, Vec<&ArcInfo>)> = map.into_iter().collect();
+  crate::parallel::map_cloned(
+    &template_lib,
+    &cells,
+    |(cell_name, info_list), mut lib| {
+      for info in info_list {
+        let options = CollectOptions {
+          gaps: GapPolicy::Keep,
+          run: Some(config_run(info.0)?),
+          plausibility: PlausibilityOptions::from_env(),
+          ..Default::default()
+        };
+        for coverage in update_cell(**info, &options, &mut lib)? {
+          println!("{coverage}");
         }
-        let lib_path = format!("pruned_active_lvf_{cell_name}.lib");
-        let mut writer = BufWriter::new(File::create(lib_path)?);
-        write!(&mut writer, "{}", template_lib)?;
       }
-      Err(_) => todo!(),
-    }
-  }
+      let lib_path = format!("pruned_active_lvf_{cell_name}.lib");
+      let mut writer = BufWriter::new(File::create(lib_path)?);
+      write!(&mut writer, "{}", lib)?;
+      Ok(())
+    },
+  )?;
   Ok(())
 }
 
 #[test]
 fn collect() -> anyhow::Result<()> {
   let template_file = "pruned_100kMC.lib";
-  match liberty_db::library::Library::<DefaultCtx>::parse_lib(&std::fs::read_to_string(
-    Path::new(template_file),
-  )?) {
-    Ok(mut template_lib) => {
-      for info in INFO {
-        update_cell(info, &mut template_lib)?;
-      }
-      let lib_path = "pruned_active_lvf_0503.lib";
-      let mut writer = BufWriter::new(File::create(lib_path)?);
-      write!(&mut writer, "{}", template_lib)?;
-    }
-    Err(_) => todo!(),
+  let mut template_lib = crate::read_lib(template_file)?;
+  let mut report = Vec::new();
+  for info in INFO {
+    let options = CollectOptions {
+      gaps: GapPolicy::Keep,
+      run: Some(config_run(info.0)?),
+      plausibility: PlausibilityOptions::from_env(),
+      ..Default::default()
+    };
+    report.extend(update_cell(info, &options, &mut template_lib)?);
+  }
+  for coverage in report.iter().filter(|c| {
+    !c.missing.is_empty() || !c.violations.is_empty() || !c.warnings.is_empty()
+  }) {
+    println!("{coverage}");
   }
+  let lib_path = "pruned_active_lvf_0503.lib";
+  let mut writer = BufWriter::new(File::create(lib_path)?);
+  write!(&mut writer, "{}", template_lib)?;
+  let report_path = "pruned_active_lvf_0503.coverage.json";
+  serde_json::to_writer_pretty(BufWriter::new(File::create(report_path)?), &report)?;
+  let manifest: BTreeMap<String, &Provenance> =
+    report.iter().map(|c| (c.provenance.key(), &c.provenance)).collect();
+  let manifest_path = "pruned_active_lvf_0503.provenance.json";
+  serde_json::to_writer_pretty(BufWriter::new(File::create(manifest_path)?), &manifest)?;
   Ok(())
 }
 
 #[test]
+#[expect(non_snake_case)]
 fn replace_timing_5kQMC() -> anyhow::Result<()> {
-  let template_file = "pruned_active_lvf.lib";
-  let data1_file = "/code/char0425/5kQMC_1/out/btdcell.lib";
-  let data2_file = "/code/char0425/5kQMC_2/out/btdcell.lib";
-  match (
-    liberty_db::library::Library::<DefaultCtx>::parse_lib(&std::fs::read_to_string(
-      Path::new(template_file),
-    )?),
-    liberty_db::library::Library::<DefaultCtx>::parse_lib(&std::fs::read_to_string(
-      Path::new(data1_file),
-    )?),
-    liberty_db::library::Library::<DefaultCtx>::parse_lib(&std::fs::read_to_string(
-      Path::new(data2_file),
-    )?),
-  ) {
-    (Ok(mut temp_lib), Ok(data1_lib), Ok(data2_lib)) => {
-      for data_lib in [data1_lib, data2_lib] {
-        for cell in data_lib.cell.into_iter() {
-          if cell.name == "HA1D1BWP30P140" {
-            continue;
-          }
-          let temp_cell = temp_lib.cell.get_mut(cell.id()).expect("cell");
-          for pin in cell.pin.into_iter() {
-            let temp_pin = temp_cell.pin.get_mut(pin.id()).expect("pin");
-            for timing in pin.timing.into_iter() {
-              let temp_timing = temp_pin.timing.get_mut(timing.id()).expect("timing");
-              temp_timing.cell_fall = timing.cell_fall;
-              temp_timing.cell_rise = timing.cell_rise;
-              temp_timing.rise_transition = timing.rise_transition;
-              temp_timing.fall_transition = timing.fall_transition;
-              temp_timing.ocv_mean_shift_cell_fall = timing.ocv_mean_shift_cell_fall;
-              temp_timing.ocv_mean_shift_cell_rise = timing.ocv_mean_shift_cell_rise;
-              temp_timing.ocv_mean_shift_rise_transition =
-                timing.ocv_mean_shift_rise_transition;
-              temp_timing.ocv_mean_shift_fall_transition =
-                timing.ocv_mean_shift_fall_transition;
-              temp_timing.ocv_std_dev_cell_fall = timing.ocv_std_dev_cell_fall;
-              temp_timing.ocv_std_dev_cell_rise = timing.ocv_std_dev_cell_rise;
-              temp_timing.ocv_std_dev_rise_transition =
-                timing.ocv_std_dev_rise_transition;
-              temp_timing.ocv_std_dev_fall_transition =
-                timing.ocv_std_dev_fall_transition;
-              temp_timing.ocv_skewness_cell_fall = timing.ocv_skewness_cell_fall;
-              temp_timing.ocv_skewness_cell_rise = timing.ocv_skewness_cell_rise;
-              temp_timing.ocv_skewness_rise_transition =
-                timing.ocv_skewness_rise_transition;
-              temp_timing.ocv_skewness_fall_transition =
-                timing.ocv_skewness_fall_transition;
-            }
-          }
-        }
-      }
-      let lib_path = "pruned_5kQMC.lib";
-      let mut writer = BufWriter::new(File::create(lib_path)?);
-      write!(&mut writer, "{}", temp_lib)?;
-    }
-    (Ok(_), Ok(_), Err(_)) => todo!(),
-    (Ok(_), Err(_), Ok(_)) => todo!(),
-    (Ok(_), Err(_), Err(_)) => todo!(),
-    (Err(_), Ok(_), Ok(_)) => todo!(),
-    (Err(_), Ok(_), Err(_)) => todo!(),
-    (Err(_), Err(_), Ok(_)) => todo!(),
-    (Err(_), Err(_), Err(_)) => todo!(),
-  }
+  let report = crate::merge::merge_files(
+    "pruned_active_lvf.lib",
+    &["/code/char0425/5kQMC_1/out/btdcell.lib", "/code/char0425/5kQMC_2/out/btdcell.lib"],
+    crate::merge::Fields::All,
+    vec![crate::merge::Skip::Cell("HA1D1BWP30P140".into())],
+    "pruned_5kQMC.lib",
+  )?;
+  print!("{report}");
   Ok(())
 }
 
 #[test]
+#[expect(non_snake_case)]
 fn replace_timing_100kMC() -> anyhow::Result<()> {
-  let template_file = "pruned_active_lvf.lib";
-  let data1_file = "/code/char0425/100kMC_1/out/btdcell.lib";
-  let data2_file = "/code/char0425/100kMC_2/out/btdcell.lib";
-  match (
-    liberty_db::library::Library::<DefaultCtx>::parse_lib(&std::fs::read_to_string(
-      Path::new(template_file),
-    )?),
-    liberty_db::library::Library::<DefaultCtx>::parse_lib(&std::fs::read_to_string(
-      Path::new(data1_file),
-    )?),
-    liberty_db::library::Library::<DefaultCtx>::parse_lib(&std::fs::read_to_string(
-      Path::new(data2_file),
-    )?),
-  ) {
-    (Ok(mut temp_lib), Ok(data1_lib), Ok(data2_lib)) => {
-      for data_lib in [data1_lib, data2_lib] {
-        for cell in data_lib.cell.into_iter() {
-          if cell.name == "HA1D1BWP30P140" {
-            continue;
-          }
-          let temp_cell = temp_lib.cell.get_mut(cell.id()).expect("cell");
-          for pin in cell.pin.into_iter() {
-            let temp_pin = temp_cell.pin.get_mut(pin.id()).expect("pin");
-            for timing in pin.timing.into_iter() {
-              let temp_timing = temp_pin.timing.get_mut(timing.id()).expect("timing");
-              temp_timing.cell_fall = timing.cell_fall;
-              temp_timing.cell_rise = timing.cell_rise;
-              temp_timing.rise_transition = timing.rise_transition;
-              temp_timing.fall_transition = timing.fall_transition;
-              temp_timing.ocv_mean_shift_cell_fall = timing.ocv_mean_shift_cell_fall;
-              temp_timing.ocv_mean_shift_cell_rise = timing.ocv_mean_shift_cell_rise;
-              temp_timing.ocv_mean_shift_rise_transition =
-                timing.ocv_mean_shift_rise_transition;
-              temp_timing.ocv_mean_shift_fall_transition =
-                timing.ocv_mean_shift_fall_transition;
-              temp_timing.ocv_std_dev_cell_fall = timing.ocv_std_dev_cell_fall;
-              temp_timing.ocv_std_dev_cell_rise = timing.ocv_std_dev_cell_rise;
-              temp_timing.ocv_std_dev_rise_transition =
-                timing.ocv_std_dev_rise_transition;
-              temp_timing.ocv_std_dev_fall_transition =
-                timing.ocv_std_dev_fall_transition;
-              temp_timing.ocv_skewness_cell_fall = timing.ocv_skewness_cell_fall;
-              temp_timing.ocv_skewness_cell_rise = timing.ocv_skewness_cell_rise;
-              temp_timing.ocv_skewness_rise_transition =
-                timing.ocv_skewness_rise_transition;
-              temp_timing.ocv_skewness_fall_transition =
-                timing.ocv_skewness_fall_transition;
-            }
-          }
-        }
-      }
-      let lib_path = "pruned_100kMC.lib";
-      let mut writer = BufWriter::new(File::create(lib_path)?);
-      write!(&mut writer, "{}", temp_lib)?;
-    }
-    (Ok(_), Ok(_), Err(_)) => todo!(),
-    (Ok(_), Err(_), Ok(_)) => todo!(),
-    (Ok(_), Err(_), Err(_)) => todo!(),
-    (Err(_), Ok(_), Ok(_)) => todo!(),
-    (Err(_), Ok(_), Err(_)) => todo!(),
-    (Err(_), Err(_), Ok(_)) => todo!(),
-    (Err(_), Err(_), Err(_)) => todo!(),
-  }
+  let report = crate::merge::merge_files(
+    "pruned_active_lvf.lib",
+    &[
+      "/code/char0425/100kMC_1/out/btdcell.lib",
+      "/code/char0425/100kMC_2/out/btdcell.lib",
+    ],
+    crate::merge::Fields::All,
+    vec![crate::merge::Skip::Cell("HA1D1BWP30P140".into())],
+    "pruned_100kMC.lib",
+  )?;
+  print!("{report}");
+  Ok(())
+}
+
+#[test]
+fn point_order() {
+  assert_eq!(PointOrder::RowMajor.position(9, 7, 7), Some((1, 2)));
+  assert_eq!(PointOrder::ColumnMajor.position(9, 7, 7), Some((2, 1)));
+  assert_eq!(PointOrder::RowMajor.position(4, 5, 1), Some((4, 0)));
+  assert_eq!(PointOrder::ColumnMajor.position(4, 5, 1), Some((4, 0)));
+  assert_eq!(PointOrder::RowMajor.position(25, 5, 5), None);
+}
+
+#[test]
+fn failed_arc_keeps_timing() -> anyhow::Result<()> {
+  let table = |name: &str| {
+    format!(
+      "{name} (t2) {{ index_1 (\"0.1, 0.2\"); index_2 (\"0.01, 0.02\"); \
+       values (\"1, 2\", \"3, 4\"); }}\n"
+    )
+  };
+  let tables: String = ["cell_rise", "rise_transition"]
+    .iter()
+    .flat_map(|name| {
+      ["", "ocv_mean_shift_", "ocv_std_dev_", "ocv_skewness_"]
+        .map(|prefix| table(&format!("{prefix}{name}")))
+    })
+    .collect();
+  let text = format!(
+    "library (small) {{\n time_unit : \"1ns\";\n cell (INVD1BWP30P140) {{\n \
+     pin (I) {{ direction : input; }}\n pin (ZN) {{\n direction : output;\n \
+     timing () {{\n related_pin : \"I\";\n timing_sense : negative_unate;\n \
+     timing_type : combinational;\n{tables} }}\n }}\n }}\n}}\n"
+  );
+  let mut lib =
+    Library::<DefaultCtx>::parse_lib(&text).map_err(|e| anyhow::anyhow!("{e:?}"))?;
+  // no results for this corner, and gaps are refused
+  let options = CollectOptions {
+    corner: Corner { pvt: "no_such_pvt", ..Default::default() },
+    ..Default::default()
+  };
+  let info = INFO
+    .iter()
+    .find(|info| info.1 == "INVD1BWP30P140" && info.6)
+    .expect("INV");
+  assert!(update_cell(*info, &options, &mut lib).is_err());
+  let cell = lib.cell.get("INVD1BWP30P140").expect("cell");
+  let timing = cell.pin.get("ZN".into()).and_then(|pin| pin.timing.iter().next());
+  let cell_rise = timing.and_then(|t| t.cell_rise.as_ref()).expect("timing kept");
+  assert!(cell_rise.comments.is_empty());
+  Ok(())
+}
+
+#[test]
+fn constraint_timing_type() -> anyhow::Result<()> {
+  let timing = |timing_type: &str| {
+    format!(
+      "timing () {{\n related_pin : \"CP\";\n timing_sense : non_unate;\n \
+       timing_type : {timing_type};\n rise_constraint (t2) {{ index_1 (\"0.1, 0.2\"); \
+       index_2 (\"0.01, 0.02\"); values (\"1, 2\", \"3, 4\"); }}\n }}\n"
+    )
+  };
+  let lib = |timings: &str| {
+    Library::<DefaultCtx>::parse_lib(&format!(
+      "library (small) {{\n cell (DFQD1BWP30P140) {{\n pin (CP) {{ direction : input; }}\n \
+       pin (D) {{\n direction : input;\n{timings} }}\n }}\n}}\n"
+    ))
+    .map_err(|e| anyhow::anyhow!("{e:?}"))
+  };
+  let timing_type = |lib: &Library<DefaultCtx>| {
+    let pin = lib.cell.get("DFQD1BWP30P140").and_then(|c| c.pin.get("D".into()));
+    arc_timing_type(
+      pin.expect("pin D").timing.iter(),
+      "CP",
+      TimingSenseType::NonUnate,
+      None,
+    )
+  };
+  let setup = lib(&timing("setup_rising"))?;
+  assert_eq!(timing_type(&setup)?, Some(TimingType::SETUP_RISING));
+  let both = lib(&(timing("setup_rising") + &timing("hold_rising")))?;
+  assert!(timing_type(&both).is_err());
+  assert_eq!(PointSource::Samples.point("63_samples.csv"), Some(63));
+  assert_eq!(PointSource::Moments.point("63_samples.csv"), None);
   Ok(())
 }
@@ -0,0 +1,53 @@
+// cargo run --bin compare --release -- [--out PREFIX] [--html] REFERENCE LIB...
+// e.g. REFERENCE = pruned_100kMC.lib, LIB = pruned_5kQMC.lib pruned_active_lvf.lib
+
+use anyhow::Context as _;
+use char22nm_preprocess::{
+  compare::{compare, write_csv, write_html},
+  read_lib,
+};
+use std::{fs::File, io::BufWriter, path::Path};
+
+fn main() -> anyhow::Result<()> {
+  let mut args = std::env::args().skip(1);
+  let mut prefix = String::from("compare");
+  let mut html = false;
+  let mut paths = Vec::new();
+  while let Some(arg) = args.next() {
+    match arg.as_str() {
+      "--out" => prefix = args.next().context("--out needs a prefix")?,
+      "--html" => html = true,
+      _ => paths.push(arg),
+    }
+  }
+  let Some((reference_path, lib_paths)) = paths.split_first() else {
+    anyhow::bail!("usage: compare [--out PREFIX] [--html] REFERENCE LIB...");
+  };
+  let reference = read_lib(reference_path)?;
+  let mut summaries = Vec::new();
+  let mut unmatched = Vec::new();
+  for path in lib_paths {
+    let name = Path::new(path)
+      .file_stem()
+      .map_or(path.clone(), |s| s.to_string_lossy().into());
+    let comparison = compare(&name, &read_lib(path)?, &reference);
+    for entry in comparison.unmatched.iter() {
+      println!("{entry}");
+    }
+    summaries.extend(comparison.summaries);
+    unmatched.extend(comparison.unmatched);
+  }
+  write_csv(&mut BufWriter::new(File::create(format!("{prefix}.csv"))?), &summaries)?;
+  serde_json::to_writer_pretty(
+    BufWriter::new(File::create(format!("{prefix}.json"))?),
+    &summaries,
+  )?;
+  serde_json::to_writer_pretty(
+    BufWriter::new(File::create(format!("{prefix}.unmatched.json"))?),
+    &unmatched,
+  )?;
+  if html {
+    write_html(&mut BufWriter::new(File::create(format!("{prefix}.html"))?), &summaries)?;
+  }
+  Ok(())
+}
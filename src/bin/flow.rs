@@ -0,0 +1,192 @@
+// cargo run --bin flow --release -- [--dry-run] [stage ...]
+
+use char22nm_preprocess::{
+  corner::Corner,
+  flow::{Flow, Stage},
+};
+
+const NLDM_LIB: &str = "/data/junzhuo/tech/tsmc/22nm/tcbn22ullbwp30p140_110b/AN61001_20201222/TSMCHOME/digital/Front_End/timing_power_noise/NLDM/tcbn22ullbwp30p140_110b/tcbn22ullbwp30p140tt0p8v25c.lib";
+const LVF_LIB: &str = "/data/junzhuo/tech/tsmc/22nm/tcbn22ullbwp30p140_110b/AN61001_20201222/TSMCHOME/digital/Front_End/LVF/CCS/tcbn22ullbwp30p140_110b/tcbn22ullbwp30p140tt0p8v25c_hm_lvf_p_ccs.lib";
+const MOMENTS_DIR: &str = "/code/ActiveLVF/char";
+
+fn cargo_test(target: &str, name: &str) -> Vec<String> {
+  let mut args = vec!["cargo", "test", "--release"];
+  match target {
+    "lib" => args.push("--lib"),
+    bin => args.extend(["--bin", bin]),
+  }
+  args.extend(["--", "--exact", name, "--nocapture"]);
+  args.into_iter().map(String::from).collect()
+}
+
+fn flow() -> Flow {
+  Flow::new(".flow_state.json")
+    .stage(
+      Stage::new("nldm_prune")
+        .input(NLDM_LIB)
+        .output("pruned.lib")
+        .command(&cargo_test("lib", "pruned_lib")),
+    )
+    .stage(
+      Stage::new("lvf_prune")
+        .input(LVF_LIB)
+        .output("pruned_lvf.lib")
+        .command(&cargo_test("lib", "pruned_lvf_lib")),
+    )
+    .stage(
+      Stage::new("lvf_template")
+        .after(&["lvf_prune"])
+        .input("pruned_lvf.lib")
+        .output("lvf.lib")
+        .command(&cargo_test("lib", "lvf_lib")),
+    )
+    // template libs, YAML configs and run scripts come out of the same pass
+    .stage(
+      Stage::new("configs")
+        .input(NLDM_LIB)
+        .output("../template")
+        .output("../config")
+        .output("../cli")
+        .command(&cargo_test("setup", "main11")),
+    )
+    .stage(
+      Stage::new("btdcell")
+        .after(&["configs"])
+        .input("../template")
+        .input("../config")
+        .input("../cli")
+        .output(MOMENTS_DIR)
+        .command(&[
+          "sh",
+          "-c",
+          "for f in ../cli/run_*.sh; do bash \"$f\" || exit 1; done",
+        ]),
+    )
+    .stage(
+      Stage::new("merge_100kMC")
+        .input("pruned_active_lvf.lib")
+        .input("/code/char0425/100kMC_1/out/btdcell.lib")
+        .input("/code/char0425/100kMC_2/out/btdcell.lib")
+        .output("pruned_100kMC.lib")
+        .command(&cargo_test("lib", "arcs::replace_timing_100kMC")),
+    )
+    .stage(
+      Stage::new("collect")
+        .after(&["btdcell", "merge_100kMC"])
+        .input("pruned_100kMC.lib")
+        .input(MOMENTS_DIR)
+        .output("pruned_active_lvf_0503.lib")
+        .output("pruned_active_lvf_0503.coverage.json")
+        .command(&cargo_test("lib", "arcs::collect")),
+    )
+    .stage(
+      Stage::new("corners")
+        .after(&["btdcell"])
+        .input(MOMENTS_DIR)
+        .input("../config")
+        .output("pruned_active_lvf.corners.json")
+        .command(&cargo_test("lib", "corner::collect_all_corners")),
+    )
+    .stage(
+      Corner::all()
+        .fold(
+          Stage::new("trend").after(&["corners"]).input("pruned_active_lvf.corners.json"),
+          |stage, corner| stage.input(format!("pruned_active_lvf_{corner}.lib")),
+        )
+        .output("pruned_active_lvf.trend.csv")
+        .command(&cargo_test("lib", "trend::trend_report")),
+    )
+    .stage(
+      Stage::new("sigma")
+        .after(&["collect"])
+        .input("pruned_active_lvf_0503.lib")
+        .output("pruned_active_lvf_0503_sigma.lib")
+        .command(&cargo_test("lib", "sigma::sigma_lib")),
+    )
+    .stage(
+      Stage::new("smooth")
+        .after(&["collect"])
+        .input("pruned_active_lvf_0503.lib")
+        .output("pruned_active_lvf_0503_smooth.lib")
+        .output("pruned_active_lvf_0503.smooth.json")
+        .command(&cargo_test("lib", "smooth::smooth_lib")),
+    )
+    .stage(
+      Stage::new("predict")
+        .after(&["collect"])
+        .input("pruned_active_lvf_0503.lib")
+        .output("pruned_active_lvf_0503_predicted.lib")
+        .output("pruned_active_lvf_0503.predicted.json")
+        .command(&cargo_test("lib", "predict::predict_lib")),
+    )
+    .stage(
+      Stage::new("pocv")
+        .after(&["collect"])
+        .input("pruned_active_lvf_0503.lib")
+        .output("pruned_active_lvf_0503.cell.pocv")
+        .output("pruned_active_lvf_0503.arc.pocv")
+        .command(&cargo_test("lib", "pocv::pocv_file")),
+    )
+    .stage(
+      Corner::all()
+        .fold(Stage::new("aocv").after(&["corners"]), |stage, corner| {
+          stage
+            .input(format!("pruned_active_lvf_{corner}.lib"))
+            .output(format!("{corner}_early.aocv"))
+            .output(format!("{corner}_late.aocv"))
+        })
+        .command(&cargo_test("lib", "aocv::aocv_files")),
+    )
+    .stage(
+      Stage::new("confidence")
+        .after(&["collect"])
+        .input("pruned_active_lvf_0503.lib")
+        .input("pruned_active_lvf_0503.coverage.json")
+        .output("pruned_active_lvf_0503.confidence.json")
+        .command(&cargo_test("lib", "confidence::confidence_report")),
+    )
+    .stage(
+      Stage::new("lint")
+        .after(&["nldm_prune", "lvf_template"])
+        .input("pruned.lib")
+        .input("lvf.lib")
+        .output("lint.jsonl")
+        .command(&[
+          "sh",
+          "-c",
+          "cargo run --release --bin lint -- pruned.lib lvf.lib > lint.jsonl",
+        ]),
+    )
+    .stage(
+      Stage::new("lvf_lint")
+        .after(&["collect"])
+        .input("pruned_active_lvf_0503.lib")
+        .output("lvf_lint.jsonl")
+        .command(&[
+          "sh",
+          "-c",
+          "cargo run --release --bin lint -- --lvf pruned_active_lvf_0503.lib > lvf_lint.jsonl",
+        ]),
+    )
+    .stage(
+      Stage::new("db")
+        .after(&["nldm_prune", "lvf_template", "collect", "lint"])
+        .input("lc.tcl")
+        .input("pruned.lib")
+        .input("lvf.lib")
+        .output("pruned.db")
+        .output("lvf.db")
+        .command(&["lc_shell", "-f", "lc.tcl"]),
+    )
+}
+
+fn main() -> anyhow::Result<()> {
+  let args: Vec<String> = std::env::args().skip(1).collect();
+  let dry_run = args.iter().any(|a| a == "--dry-run" || a == "-n");
+  let targets: Vec<&str> = args
+    .iter()
+    .map(String::as_str)
+    .filter(|a| !a.starts_with('-'))
+    .collect();
+  flow().run(&targets, dry_run)
+}
@@ -0,0 +1,48 @@
+// cargo run --bin libdiff --release -- [--abs TOL] [--rel TOL] OLD NEW
+// e.g. OLD = pruned_baseline.lib, NEW = pruned_active_lvf.lib
+// One JSON object per difference on stdout. Exit status as diff(1): 0 when the
+// libraries match, 1 when there is any difference, 2 on bad usage or a library
+// that cannot be read.
+
+use anyhow::Context as _;
+use char22nm_preprocess::{
+  diff::{diff, Tolerance},
+  read_lib,
+};
+use std::process::ExitCode;
+
+/// Whether the libraries differ
+fn run() -> anyhow::Result<bool> {
+  let mut args = std::env::args().skip(1);
+  let mut tolerance = Tolerance::default();
+  let mut paths = Vec::new();
+  while let Some(arg) = args.next() {
+    match arg.as_str() {
+      "--abs" => tolerance.abs = args.next().context("--abs needs a value")?.parse()?,
+      "--rel" => tolerance.rel = args.next().context("--rel needs a value")?.parse()?,
+      _ => paths.push(arg),
+    }
+  }
+  let [old_path, new_path] = paths.as_slice() else {
+    anyhow::bail!("usage: libdiff [--abs TOL] [--rel TOL] OLD NEW");
+  };
+  let differences = diff(&read_lib(old_path)?, &read_lib(new_path)?, &tolerance);
+  for difference in differences.iter() {
+    println!("{}", serde_json::to_string(difference)?);
+  }
+  if !differences.is_empty() {
+    eprintln!("{} differences between {old_path} and {new_path}", differences.len());
+  }
+  Ok(!differences.is_empty())
+}
+
+fn main() -> ExitCode {
+  match run() {
+    Ok(false) => ExitCode::SUCCESS,
+    Ok(true) => ExitCode::from(1),
+    Err(e) => {
+      eprintln!("Error: {e:?}");
+      ExitCode::from(2)
+    }
+  }
+}
@@ -0,0 +1,47 @@
+// cargo run --bin lint --release -- [--lvf] LIB...
+// e.g. LIB = pruned.lib lvf.lib, before lc.tcl compiles them,
+// or --lvf pruned_active_lvf_0503.lib before release
+// One JSON object per finding on stdout, exit status 1 when there is any.
+
+use char22nm_preprocess::{
+  lint::lint_text,
+  lvf_lint::{incomplete_arcs, lvf_completeness, LIBRARY_ATTRIBUTES},
+};
+
+fn main() -> anyhow::Result<()> {
+  let mut lvf = false;
+  let mut paths = Vec::new();
+  for arg in std::env::args().skip(1) {
+    match arg.as_str() {
+      "--lvf" => lvf = true,
+      _ => paths.push(arg),
+    }
+  }
+  if paths.is_empty() {
+    anyhow::bail!("usage: lint [--lvf] LIB...");
+  }
+  let mut count = 0;
+  for path in paths.iter() {
+    let text =
+      std::fs::read_to_string(path).map_err(|e| anyhow::anyhow!("read {path}: {e}"))?;
+    let mut findings =
+      lint_text(&text).map_err(|e| anyhow::anyhow!("parse {path}: {e}"))?;
+    if lvf {
+      let lvf_findings = lvf_completeness(&text, &LIBRARY_ATTRIBUTES);
+      for arc in incomplete_arcs(&lvf_findings) {
+        eprintln!("{path}: incomplete LVF {arc}");
+      }
+      findings.extend(lvf_findings);
+    }
+    for finding in findings.iter() {
+      println!("{}", serde_json::json!({ "lib": path, "finding": finding }));
+      eprintln!("{path}: {finding}");
+    }
+    count += findings.len();
+  }
+  if count > 0 {
+    eprintln!("{count} findings");
+    std::process::exit(1);
+  }
+  Ok(())
+}
@@ -9,7 +9,8 @@ use std::{
   path::Path,
 };
 
-use char22nm_preprocess::{Config, CELL_GROUP, PVT, RUN};
+use char22nm_preprocess::{parallel, Config, CELL_GROUP, PVT, RUN};
+use rayon::prelude::*;
 
 fn main() -> anyhow::Result<()> {
   #[derive(Debug, serde::Serialize)]
@@ -23,9 +24,7 @@ fn main() -> anyhow::Result<()> {
   fn process_one(args: &(&str, &str, f32, f32)) -> anyhow::Result<(String, TableInfo)> {
     let (pvt_name, p, v, t) = args;
     let file_name = format!("/data/junzhuo/tech/tsmc/22nm/tcbn22ullbwp30p140_110b/AN61001_20201222/TSMCHOME/digital/Front_End/timing_power_noise/NLDM/tcbn22ullbwp30p140_110b/tcbn22ullbwp30p140{pvt_name}.lib") ;
-    let library = liberty_db::library::Library::<DefaultCtx>::parse_lib(
-      &fs::read_to_string(file_name)?,
-    )?;
+    let library = char22nm_preprocess::read_lib(file_name)?;
     let cell_dff = library.cell.get("DFCNQD1BWP30P140").context("Failed to get cell")?;
     let pin_d = cell_dff.pin.get("D".into()).context("Failed to get pin D")?;
     let timing = pin_d
@@ -50,7 +49,9 @@ fn main() -> anyhow::Result<()> {
       },
     ))
   }
-  let infos = PVT.iter().map(process_one).collect::<Result<BTreeMap<_, _>, _>>()?;
+  let infos = parallel::install(|| {
+    PVT.par_iter().map(process_one).collect::<Result<BTreeMap<_, _>, _>>()
+  })??;
   let writer = BufWriter::new(File::create("DFCNQD1BWP30P140.json")?);
   serde_json::to_writer_pretty(writer, &infos)?;
   Ok(())
@@ -69,9 +70,8 @@ fn main11() -> anyhow::Result<()> {
   let run_dir = fs::canonicalize(&Path::new("../run"))?;
   let cpu_num: usize = 32;
   let mut task_list = Vec::new();
-  if let Ok(mut library) = liberty_db::library::Library::<DefaultCtx>::parse_lib(
-    &std::fs::read_to_string(Path::new(file_name))?,
-  ) {
+  let mut library = char22nm_preprocess::read_lib(file_name)?;
+  {
     let mut _library = library.clone();
     _library.cell.clear();
     for (cell_group, (pin_name, related, when_str, rise), cell_names) in CELL_GROUP {
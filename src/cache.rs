@@ -0,0 +1,172 @@
+//! On-disk cache of parsed libraries.
+//!
+//! A parsed library is stored with bincode under `LVF_CACHE_DIR`, `target/lib-cache`
+//! when unset, in a file named after the sha256 of its canonical path. The entry
+//! starts with the [`Fingerprint`] of the source, so a changed size, mtime or
+//! content makes [`read_lib`] parse again and replace it. An empty `LVF_CACHE_DIR`
+//! turns the cache off.
+use crate::flow::Fingerprint;
+use liberty_db::{DefaultCtx, Library};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+  fs::{self, File},
+  io::{BufReader, BufWriter},
+  path::{Path, PathBuf},
+  sync::atomic::{AtomicUsize, Ordering},
+};
+
+pub const CACHE_DIR_VAR: &str = "LVF_CACHE_DIR";
+const DEFAULT_CACHE_DIR: &str = "target/lib-cache";
+/// Distinguishes the partial entries written by the threads of one process
+static PARTIAL: AtomicUsize = AtomicUsize::new(0);
+/// Bumped whenever the serialized form may change
+const FORMAT: &str = concat!("1/", env!("CARGO_PKG_VERSION"));
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Header {
+  format: String,
+  source: PathBuf,
+  fingerprint: Fingerprint,
+}
+
+pub fn cache_dir() -> Option<PathBuf> {
+  match std::env::var(CACHE_DIR_VAR) {
+    Ok(dir) if dir.is_empty() => None,
+    Ok(dir) => Some(dir.into()),
+    Err(_) => Some(DEFAULT_CACHE_DIR.into()),
+  }
+}
+
+/// Cache file of `source` below `dir`
+pub fn entry_path(dir: &Path, source: &Path) -> PathBuf {
+  let hash: String = Sha256::digest(source.as_os_str().as_encoded_bytes())
+    .iter()
+    .map(|b| format!("{b:02x}"))
+    .collect();
+  dir.join(format!("{hash}.bin"))
+}
+
+pub fn parse_lib(path: &Path) -> anyhow::Result<Library<DefaultCtx>> {
+  let text = fs::read_to_string(path)
+    .map_err(|e| anyhow::anyhow!("read {}: {e}", path.display()))?;
+  Library::parse_lib(&text)
+    .map_err(|e| anyhow::anyhow!("parse {}: {e:?}", path.display()))
+}
+
+/// The cached library of `source` when it is still of the file's current content
+fn load(entry: &Path, source: &Path) -> Option<Library<DefaultCtx>> {
+  let mut reader = BufReader::new(File::open(entry).ok()?);
+  let header: Header = bincode::deserialize_from(&mut reader).ok()?;
+  if header.format != FORMAT || header.source != source {
+    return None;
+  }
+  let current = Fingerprint::of(source, Some(&header.fingerprint)).ok()?;
+  if current.sha256 != header.fingerprint.sha256 {
+    return None;
+  }
+  bincode::deserialize_from(&mut reader).ok()
+}
+
+fn store(
+  entry: &Path,
+  source: &Path,
+  fingerprint: Fingerprint,
+  library: &Library<DefaultCtx>,
+) -> anyhow::Result<()> {
+  if let Some(dir) = entry.parent() {
+    fs::create_dir_all(dir)?;
+  }
+  // written aside and renamed, so parallel readers never see half an entry
+  let partial = entry.with_extension(format!(
+    "{}.{}.tmp",
+    std::process::id(),
+    PARTIAL.fetch_add(1, Ordering::Relaxed)
+  ));
+  let header = Header {
+    format: FORMAT.into(),
+    source: source.into(),
+    fingerprint,
+  };
+  let mut writer = BufWriter::new(File::create(&partial)?);
+  bincode::serialize_into(&mut writer, &header)?;
+  bincode::serialize_into(&mut writer, library)?;
+  drop(writer);
+  fs::rename(&partial, entry)?;
+  Ok(())
+}
+
+/// `path` parsed, or loaded from the cache in `dir` when it did not change
+pub fn read_lib_in(dir: &Path, path: &Path) -> anyhow::Result<Library<DefaultCtx>> {
+  let source = fs::canonicalize(path)
+    .map_err(|e| anyhow::anyhow!("read {}: {e}", path.display()))?;
+  let entry = entry_path(dir, &source);
+  if let Some(library) = load(&entry, &source) {
+    return Ok(library);
+  }
+  let fingerprint = Fingerprint::of(&source, None)?;
+  let library = parse_lib(&source)?;
+  if let Err(e) = store(&entry, &source, fingerprint, &library) {
+    eprintln!("cache {}: {e}", entry.display());
+  }
+  Ok(library)
+}
+
+/// `path` through the cache in [`cache_dir`]
+pub fn read_lib(path: impl AsRef<Path>) -> anyhow::Result<Library<DefaultCtx>> {
+  match cache_dir() {
+    Some(dir) => read_lib_in(&dir, path.as_ref()),
+    None => parse_lib(path.as_ref()),
+  }
+}
+
+#[test]
+fn cache_invalidation() -> anyhow::Result<()> {
+  let dir = std::env::temp_dir().join(format!("lib-cache-{}", std::process::id()));
+  fs::create_dir_all(&dir)?;
+  let lib_path = dir.join("small.lib");
+  let lib = |cell: &str| {
+    format!(
+      "library (small) {{
+  time_unit : \"1ns\";
+  lu_table_template (t2) {{
+    variable_1 : input_net_transition;
+    variable_2 : total_output_net_capacitance;
+    index_1 (\"1, 2\");
+    index_2 (\"1, 2\");
+  }}
+  cell ({cell}) {{
+    area : 1;
+    pin (ZN) {{
+      direction : output;
+      function : \"!I\";
+      timing () {{
+        related_pin : \"I\";
+        timing_sense : negative_unate;
+        cell_rise (t2) {{
+          index_1 (\"1, 2\");
+          index_2 (\"1, 2\");
+          values (\"1, 2\", \"3, 4\");
+        }}
+      }}
+    }}
+    pin (I) {{
+      direction : input;
+    }}
+  }}
+}}
+"
+    )
+  };
+  fs::write(&lib_path, lib("INV"))?;
+  let parsed = read_lib_in(&dir, &lib_path)?;
+  let entry = entry_path(&dir, &fs::canonicalize(&lib_path)?);
+  assert!(entry.exists());
+  let cached = load(&entry, &fs::canonicalize(&lib_path)?).expect("cached");
+  assert_eq!(cached.to_string(), parsed.to_string());
+  fs::write(&lib_path, lib("BUF"))?;
+  assert!(load(&entry, &fs::canonicalize(&lib_path)?).is_none());
+  assert!(read_lib_in(&dir, &lib_path)?.cell.get("BUF").is_some());
+  fs::remove_dir_all(&dir)?;
+  Ok(())
+}
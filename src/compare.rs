@@ -0,0 +1,339 @@
+//! Accuracy of LVF libraries against a reference, e.g. 5k/10k QMC against 100k MC.
+//!
+//! Every OCV entry present in both a library and the reference contributes an
+//! absolute and a relative error (the latter skipped where the reference is 0),
+//! aggregated by arc, cell, `CELL_GROUP` family, PVT and table kind. Tables on a
+//! different grid than the reference, and cells, pins, arcs and tables the
+//! reference lacks, are listed instead of compared.
+use crate::{family_of, pvt_of};
+use liberty_db::{timing::TimingTableLookUp, DefaultCtx, Library};
+use serde::Serialize;
+use std::{collections::BTreeMap, fmt, io::Write};
+
+pub const GROUP_BY: [&str; 5] = ["arc", "cell", "family", "pvt", "table"];
+pub const COMPONENTS: [&str; 3] = ["mean_shift", "std_dev", "skewness"];
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Summary {
+  pub library: String,
+  pub group_by: &'static str,
+  pub key: String,
+  pub component: &'static str,
+  pub count: usize,
+  pub mean_abs: f64,
+  pub max_abs: f64,
+  pub mean_rel: f64,
+  pub max_rel: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Reason {
+  NotInReference,
+  GridMismatch,
+}
+
+/// Part of a library that could not be compared with the reference
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Unmatched {
+  pub library: String,
+  pub path: String,
+  pub reason: Reason,
+}
+
+impl fmt::Display for Unmatched {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let reason = match self.reason {
+      Reason::NotInReference => "not in the reference",
+      Reason::GridMismatch => "grid differs from the reference",
+    };
+    write!(f, "{}: {}: {reason}", self.library, self.path)
+  }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Comparison {
+  pub summaries: Vec<Summary>,
+  pub unmatched: Vec<Unmatched>,
+}
+
+impl Summary {
+  fn add(&mut self, abs: f64, rel: Option<f64>, rel_count: &mut usize) {
+    self.count += 1;
+    self.mean_abs += abs;
+    self.max_abs = self.max_abs.max(abs);
+    if let Some(rel) = rel {
+      *rel_count += 1;
+      self.mean_rel += rel;
+      self.max_rel = self.max_rel.max(rel);
+    }
+  }
+}
+
+/// The LVF index, the nominal one when the table has none of its own
+pub(crate) fn lvf_index<'a>(lvf: &'a [f64], nominal: &'a [f64]) -> &'a [f64] {
+  if lvf.is_empty() {
+    nominal
+  } else {
+    lvf
+  }
+}
+
+/// Whether both tables have the same indices and LVF indices, and as many points
+fn same_grid(
+  table: &TimingTableLookUp<DefaultCtx>,
+  reference: &TimingTableLookUp<DefaultCtx>,
+) -> bool {
+  let same = |a: &[f64], b: &[f64]| {
+    a.len() == b.len()
+      && a
+        .iter()
+        .zip(b)
+        .all(|(a, b)| (a - b).abs() <= 1e-9 * a.abs().max(b.abs()))
+  };
+  same(&table.index_1, &reference.index_1)
+    && same(&table.index_2, &reference.index_2)
+    && same(
+      lvf_index(&table.lvf_index_1, &table.index_1),
+      lvf_index(&reference.lvf_index_1, &reference.index_1),
+    )
+    && same(
+      lvf_index(&table.lvf_index_2, &table.index_2),
+      lvf_index(&reference.lvf_index_2, &reference.index_2),
+    )
+    && table.lvf_values.len() == reference.lvf_values.len()
+}
+
+fn components(table: &TimingTableLookUp<DefaultCtx>, idx: usize) -> [f64; 3] {
+  let lvf = &table.lvf_values[idx];
+  [lvf.mean - table.values[idx], lvf.std_dev, lvf.skewness]
+}
+
+/// Summaries of `lib` named `name` against `reference`, sorted by group, key and
+/// component, and what of `lib` could not be compared
+pub fn compare(
+  name: &str,
+  lib: &Library<DefaultCtx>,
+  reference: &Library<DefaultCtx>,
+) -> Comparison {
+  let pvt = pvt_of(&lib.name).unwrap_or("unknown");
+  let mut acc: BTreeMap<(usize, String, usize), (Summary, usize)> = BTreeMap::new();
+  let mut unmatched = Vec::new();
+  let mut push = |path: String, reason| {
+    unmatched.push(Unmatched { library: name.to_string(), path, reason })
+  };
+  for cell in lib.cell.iter() {
+    let Some(ref_cell) = reference.cell.get(&cell.name) else {
+      push(cell.name.clone(), Reason::NotInReference);
+      continue;
+    };
+    let family = family_of(&cell.name).unwrap_or("unknown");
+    for pin in cell.pin.iter() {
+      let pin_name = pin.name.to_string();
+      let Some(ref_pin) = ref_cell.pin.get(pin.name.as_ref()) else {
+        push(format!("{}/{pin_name}", cell.name), Reason::NotInReference);
+        continue;
+      };
+      for timing in pin.timing.iter() {
+        let path = crate::lint::timing_path(&cell.name, &pin_name, timing);
+        let Some(ref_timing) = ref_pin.timing.get(
+          timing.related_pin.as_ref(),
+          timing.timing_sense.as_ref(),
+          timing.timing_type.as_ref(),
+          timing.when.as_ref(),
+        ) else {
+          push(path, Reason::NotInReference);
+          continue;
+        };
+        let mut arc = format!("{} {}->{}", cell.name, timing.related_pin, pin.name);
+        if let Some(when) = &timing.when {
+          arc.push_str(&format!(" when {when}"));
+        }
+        for (kind, table, ref_table) in [
+          ("cell_rise", &timing.cell_rise, &ref_timing.cell_rise),
+          ("cell_fall", &timing.cell_fall, &ref_timing.cell_fall),
+          ("rise_transition", &timing.rise_transition, &ref_timing.rise_transition),
+          ("fall_transition", &timing.fall_transition, &ref_timing.fall_transition),
+        ] {
+          let Some(table) = table.as_ref().filter(|t| !t.lvf_values.is_empty()) else {
+            continue;
+          };
+          let Some(ref_table) = ref_table else {
+            push(format!("{path}/{kind}"), Reason::NotInReference);
+            continue;
+          };
+          if !same_grid(table, ref_table) {
+            push(format!("{path}/{kind}"), Reason::GridMismatch);
+            continue;
+          }
+          for idx in 0..table.lvf_values.len() {
+            let (got, want) = (components(table, idx), components(ref_table, idx));
+            for (c, component) in COMPONENTS.iter().enumerate() {
+              let abs = (got[c] - want[c]).abs();
+              let rel = (want[c] != 0.0).then(|| abs / want[c].abs());
+              let keys = [arc.as_str(), cell.name.as_str(), family, pvt, kind];
+              for (g, key) in keys.into_iter().enumerate() {
+                let (summary, rel_count) =
+                  acc.entry((g, key.to_string(), c)).or_insert_with(|| {
+                    let summary = Summary {
+                      library: name.to_string(),
+                      group_by: GROUP_BY[g],
+                      key: key.to_string(),
+                      component,
+                      ..Default::default()
+                    };
+                    (summary, 0)
+                  });
+                summary.add(abs, rel, rel_count);
+              }
+            }
+          }
+        }
+      }
+    }
+  }
+  let summaries = acc
+    .into_values()
+    .map(|(mut summary, rel_count)| {
+      summary.mean_abs /= summary.count as f64;
+      summary.mean_rel =
+        if rel_count == 0 { f64::NAN } else { summary.mean_rel / rel_count as f64 };
+      summary
+    })
+    .collect();
+  Comparison { summaries, unmatched }
+}
+
+pub fn write_csv(writer: &mut impl Write, summaries: &[Summary]) -> std::io::Result<()> {
+  writeln!(
+    writer,
+    "library,group_by,key,component,count,mean_abs,max_abs,mean_rel,max_rel"
+  )?;
+  for s in summaries {
+    writeln!(
+      writer,
+      "{},{},\"{}\",{},{},{:e},{:e},{:e},{:e}",
+      s.library,
+      s.group_by,
+      s.key,
+      s.component,
+      s.count,
+      s.mean_abs,
+      s.max_abs,
+      s.mean_rel,
+      s.max_rel
+    )?;
+  }
+  Ok(())
+}
+
+/// One table per grouping, the libraries side by side
+pub fn write_html(writer: &mut impl Write, summaries: &[Summary]) -> std::io::Result<()> {
+  writeln!(
+    writer,
+    "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>LVF accuracy</title>"
+  )?;
+  writeln!(writer, "<style>table{{border-collapse:collapse}}td,th{{border:1px solid #999;padding:2px 6px}}td{{text-align:right}}</style>")?;
+  writeln!(writer, "</head><body>")?;
+  for group_by in GROUP_BY {
+    writeln!(writer, "<h2>by {group_by}</h2>\n<table>")?;
+    writeln!(writer, "<tr><th>{group_by}</th><th>library</th><th>component</th><th>count</th><th>mean abs</th><th>max abs</th><th>mean rel</th><th>max rel</th></tr>")?;
+    let mut rows: Vec<&Summary> =
+      summaries.iter().filter(|s| s.group_by == group_by).collect();
+    rows.sort_by(|a, b| {
+      (&a.key, a.component, &a.library).cmp(&(&b.key, b.component, &b.library))
+    });
+    for s in rows {
+      writeln!(
+        writer,
+        "<tr><th>{}</th><th>{}</th><th>{}</th><td>{}</td><td>{:.3e}</td><td>{:.3e}</td><td>{:.2}%</td><td>{:.2}%</td></tr>",
+        s.key.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;"),
+        s.library,
+        s.component,
+        s.count,
+        s.mean_abs,
+        s.max_abs,
+        100.0 * s.mean_rel,
+        100.0 * s.max_rel
+      )?;
+    }
+    writeln!(writer, "</table>")?;
+  }
+  writeln!(writer, "</body></html>")
+}
+
+#[test]
+fn compare_summary() {
+  let mut summary = Summary::default();
+  let mut rel_count = 0;
+  summary.add(0.1, Some(0.01), &mut rel_count);
+  summary.add(0.3, None, &mut rel_count);
+  assert_eq!((summary.count, rel_count), (2, 1));
+  assert_eq!((summary.max_abs, summary.max_rel), (0.3, 0.01));
+  let mut out = Vec::new();
+  write_csv(
+    &mut out,
+    &[Summary {
+      library: "5kQMC".into(),
+      group_by: "cell",
+      key: "INVD1".into(),
+      component: "std_dev",
+      count: 2,
+      mean_abs: 0.5,
+      max_abs: 1.0,
+      mean_rel: 0.25,
+      max_rel: 0.5,
+    }],
+  )
+  .unwrap();
+  assert_eq!(
+    String::from_utf8(out).unwrap().lines().nth(1),
+    Some("5kQMC,cell,\"INVD1\",std_dev,2,5e-1,1e0,2.5e-1,5e-1")
+  );
+}
+
+#[test]
+fn compare_unmatched() -> anyhow::Result<()> {
+  let lib = |cells: &[(&str, &str)]| {
+    let cells: String = cells
+      .iter()
+      .map(|(cell, index_1)| {
+        let tables: String = ["", "ocv_mean_shift_", "ocv_std_dev_", "ocv_skewness_"]
+          .map(|prefix| {
+            format!(
+              "{prefix}cell_rise (t2) {{ index_1 (\"{index_1}\"); \
+                 index_2 (\"0.01, 0.02\"); values (\"1, 2\", \"3, 4\"); }}\n"
+            )
+          })
+          .concat();
+        format!(
+          " cell ({cell}) {{\n pin (I) {{ direction : input; }}\n pin (ZN) {{\n \
+           direction : output;\n timing () {{\n related_pin : \"I\";\n \
+           timing_sense : negative_unate;\n timing_type : combinational;\n\
+           {tables} }}\n }}\n }}\n"
+        )
+      })
+      .collect();
+    Library::<DefaultCtx>::parse_lib(&format!(
+      "library (tt0p8v25c) {{\n time_unit : \"1ns\";\n{cells}}}\n"
+    ))
+    .map_err(|e| anyhow::anyhow!("{e:?}"))
+  };
+  let reference = lib(&[("INVD1BWP30P140", "0.1, 0.2")])?;
+  let same = compare("same", &reference, &reference);
+  assert_eq!((same.summaries.len(), same.unmatched.len()), (15, 0));
+  let other = lib(&[("INVD1BWP30P140", "0.1, 0.3"), ("ND2D1BWP30P140", "0.1, 0.2")])?;
+  let comparison = compare("other", &other, &reference);
+  assert_eq!(comparison.summaries, []);
+  let unmatched: Vec<String> =
+    comparison.unmatched.iter().map(ToString::to_string).collect();
+  assert_eq!(
+    unmatched,
+    [
+      "other: INVD1BWP30P140/ZN/timing(I negative_unate combinational)/cell_rise: \
+       grid differs from the reference",
+      "other: ND2D1BWP30P140: not in the reference",
+    ]
+  );
+  Ok(())
+}
@@ -0,0 +1,180 @@
+//! Confidence intervals of the collected moments from the sample count.
+//!
+//! Normal-theory standard errors for `n` samples: `std_dev / sqrt(n)` for the
+//! mean, `std_dev / sqrt(2 (n - 1))` for the std dev and
+//! `sqrt(6 n (n - 1) / ((n - 2) (n + 1) (n + 3)))` for the skewness.
+//! Only simulated points get intervals, gap-filled ones are left out.
+use crate::arcs::{lvf_tables, ArcInfo};
+use liberty_db::{timing::LVFValue, DefaultCtx, Library};
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Interval {
+  pub lo: f64,
+  pub hi: f64,
+}
+
+impl Interval {
+  fn around(value: f64, std_err: f64, z: f64) -> Self {
+    Self { lo: value - z * std_err, hi: value + z * std_err }
+  }
+  pub fn contains(&self, x: f64) -> bool {
+    self.lo <= x && x <= self.hi
+  }
+  pub fn width(&self) -> f64 {
+    self.hi - self.lo
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ConfidenceOptions {
+  /// standard normal quantile of the interval, 1.96 for 95%
+  pub z: f64,
+  /// skewness intervals wider than this are reported
+  pub max_skewness_width: f64,
+}
+
+impl Default for ConfidenceOptions {
+  fn default() -> Self {
+    Self { z: 1.96, max_skewness_width: 0.2 }
+  }
+}
+
+/// Intervals of mean, std dev and skewness of a point, in the unit of `lvf`
+pub fn intervals(lvf: &LVFValue, samples: usize, z: f64) -> [Interval; 3] {
+  let n = samples as f64;
+  let skewness_err = (6.0 * n * (n - 1.0) / ((n - 2.0) * (n + 1.0) * (n + 3.0))).sqrt();
+  [
+    Interval::around(lvf.mean, lvf.std_dev / n.sqrt(), z),
+    Interval::around(lvf.std_dev, lvf.std_dev / (2.0 * (n - 1.0)).sqrt(), z),
+    Interval::around(lvf.skewness, skewness_err, z),
+  ]
+}
+
+/// What an LVF table was collected from
+#[derive(Debug, Clone, Default)]
+pub struct Collected {
+  /// sample count of the run the table was collected with
+  pub samples: usize,
+  /// row-major positions without a simulation result
+  pub missing: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PointConfidence {
+  pub cell: String,
+  pub arc: String,
+  pub table: &'static str,
+  /// row-major position in the table
+  pub point: usize,
+  pub samples: usize,
+  pub mean: Interval,
+  pub std_dev: Interval,
+  pub skewness: Interval,
+  pub warnings: Vec<String>,
+}
+
+/// Intervals of the simulated points of the LVF tables of `infos`, `collected`
+/// tells for each arc and table name how it was collected.
+pub fn confidence(
+  lib: &Library<DefaultCtx>,
+  infos: &[ArcInfo],
+  mut collected: impl FnMut(&ArcInfo, &str) -> anyhow::Result<Collected>,
+  options: &ConfidenceOptions,
+) -> anyhow::Result<Vec<PointConfidence>> {
+  let mut report = Vec::new();
+  for info in infos {
+    for (table_name, table) in lvf_tables(*info, lib)? {
+      let Collected { samples: n, missing } = collected(info, table_name)?;
+      if n < 3 {
+        anyhow::bail!(
+          "cell {} arc{} {table_name}: {n} samples are too few",
+          info.1,
+          info.4
+        );
+      }
+      for (point, lvf) in table.lvf_values.iter().enumerate() {
+        if missing.contains(&point) {
+          continue;
+        }
+        let [mean, std_dev, skewness] = intervals(lvf, n, options.z);
+        let mut warnings = Vec::new();
+        if skewness.contains(0.0) {
+          warnings.push("skewness interval includes zero".to_string());
+        }
+        if skewness.width() > options.max_skewness_width {
+          warnings.push(format!(
+            "skewness interval {:.3} is wider than {}",
+            skewness.width(),
+            options.max_skewness_width
+          ));
+        }
+        report.push(PointConfidence {
+          cell: info.1.to_string(),
+          arc: info.4.to_string(),
+          table: table_name,
+          point,
+          samples: n,
+          mean,
+          std_dev,
+          skewness,
+          warnings,
+        });
+      }
+    }
+  }
+  Ok(report)
+}
+
+#[test]
+fn confidence_intervals() {
+  let lvf = LVFValue { mean: 10.0, std_dev: 1.0, skewness: 0.04 };
+  let [mean, std_dev, skewness] = intervals(&lvf, 10001, 2.0);
+  assert!((mean.width() - 0.04).abs() < 1e-4);
+  assert!((std_dev.width() - 4.0 / 20000f64.sqrt()).abs() < 1e-12);
+  // the standard error of the skewness is about sqrt(6 / n)
+  assert!((skewness.width() - 4.0 * (6.0 / 10001f64).sqrt()).abs() < 1e-4);
+  assert!(skewness.contains(0.0) && !skewness.contains(0.2));
+}
+
+#[test]
+fn confidence_report() -> anyhow::Result<()> {
+  use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+  };
+  let lib_path = "pruned_active_lvf_0503.lib";
+  let lib = crate::read_lib(lib_path)?;
+  // written by arcs::collect next to the library
+  let coverage_path = "pruned_active_lvf_0503.coverage.json";
+  let coverage: Vec<serde_json::Value> =
+    serde_json::from_reader(BufReader::new(File::open(coverage_path)?))?;
+  let collected = |info: &ArcInfo, table: &str| -> anyhow::Result<Collected> {
+    let entry = coverage
+      .iter()
+      .find(|c| c["cell"] == info.1 && c["arc"] == info.4 && c["table"] == table)
+      .ok_or_else(|| {
+        anyhow::anyhow!("{coverage_path}: no cell {} arc{} {table}", info.1, info.4)
+      })?;
+    let samples = entry["provenance"]["run"]["samples"].as_u64().ok_or_else(|| {
+      anyhow::anyhow!("{coverage_path}: cell {} arc{} {table} has no run", info.1, info.4)
+    })?;
+    let missing = serde_json::from_value(entry["missing"].clone())?;
+    Ok(Collected { samples: samples as usize, missing })
+  };
+  let report =
+    confidence(&lib, &crate::arcs::INFO, collected, &ConfidenceOptions::default())?;
+  for point in report.iter().filter(|p| !p.warnings.is_empty()) {
+    println!(
+      "{} arc{} {} {}: {}",
+      point.cell,
+      point.arc,
+      point.table,
+      point.point,
+      point.warnings.join(", ")
+    );
+  }
+  let report_path = "pruned_active_lvf_0503.confidence.json";
+  serde_json::to_writer_pretty(BufWriter::new(File::create(report_path)?), &report)?;
+  Ok(())
+}
@@ -0,0 +1,260 @@
+//! Collection over every `RUN` x `PVT` corner that has btdcell results.
+//!
+//! Each corner fills its own template, `{stem}_{pvt}.lib`, and is written as
+//! `{out}_{run}_{pvt}.lib` with the library renamed after the run, so libraries of
+//! different corners and sampling runs never overwrite each other.
+use crate::{
+  arcs::{update_cell, ArcInfo, CollectOptions, Coverage},
+  provenance::read_run,
+  read_lib, PVT, RUN,
+};
+use anyhow::bail;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::{
+  fmt,
+  fs::File,
+  io::{BufWriter, Write},
+  path::{Path, PathBuf},
+};
+
+const MOMENTS_DIR: &str = "/code/ActiveLVF/char";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Corner {
+  pub run: &'static str,
+  pub pvt: &'static str,
+}
+
+impl Default for Corner {
+  fn default() -> Self {
+    Self { run: RUN[0].0, pvt: "tt0p8v25c" }
+  }
+}
+
+impl fmt::Display for Corner {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}_{}", self.run, self.pvt)
+  }
+}
+
+impl Corner {
+  /// Every `RUN` x `PVT` pair
+  pub fn all() -> impl Iterator<Item = Corner> {
+    RUN
+      .iter()
+      .flat_map(|(run, _, _)| PVT.iter().map(move |(pvt, _, _, _)| Corner { run, pvt }))
+  }
+  /// Per-point results of `cell_group`, `{MOMENTS_DIR}/{run}/{cell_group}/{pvt}`,
+  /// or for the default run also the single-run layout
+  /// `{MOMENTS_DIR}/{cell_group}/{pvt}` without a run level
+  pub fn moments_dir(&self, cell_group: &str) -> PathBuf {
+    let per_run = Path::new(MOMENTS_DIR).join(self.run).join(cell_group).join(self.pvt);
+    if per_run.exists() || self.run != RUN[0].0 {
+      per_run
+    } else {
+      Path::new(MOMENTS_DIR).join(cell_group).join(self.pvt)
+    }
+  }
+  pub fn config_path(&self, cell_group: &str) -> String {
+    format!("../config/{cell_group}_{}_{}.yaml", self.run, self.pvt)
+  }
+  pub fn template(&self, stem: &str) -> String {
+    format!("{stem}_{}.lib", self.pvt)
+  }
+  /// Whether any arc of `infos` has a result directory
+  pub fn has_results(&self, infos: &[ArcInfo]) -> bool {
+    infos.iter().any(|info| self.arc_dir(info).exists())
+  }
+  fn arc_dir(&self, info: &ArcInfo) -> PathBuf {
+    self.moments_dir(info.0).join(info.1).join(format!("arc{}", info.4))
+  }
+}
+
+/// What one corner got
+#[derive(Debug, Clone, Serialize)]
+pub struct CornerReport {
+  pub corner: Corner,
+  /// written library, `None` when the template or every result is missing
+  pub lib: Option<String>,
+  /// `cell arcN` without a result directory, left untouched in the library
+  pub missing_arcs: Vec<String>,
+  pub coverage: Vec<Coverage>,
+}
+
+impl CornerReport {
+  pub fn is_complete(&self) -> bool {
+    self.lib.is_some()
+      && self.missing_arcs.is_empty()
+      && self.coverage.iter().all(|c| c.missing.is_empty())
+  }
+}
+
+impl fmt::Display for CornerReport {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}: ", self.corner)?;
+    let Some(lib) = &self.lib else {
+      if self.missing_arcs.is_empty() {
+        return write!(f, "no template");
+      }
+      return write!(f, "no results, {} arcs missing", self.missing_arcs.len());
+    };
+    let incomplete = self.coverage.iter().filter(|c| !c.missing.is_empty()).count();
+    write!(
+      f,
+      "{lib}, {} arcs missing, {incomplete} of {} tables incomplete",
+      self.missing_arcs.len(),
+      self.coverage.len()
+    )
+  }
+}
+
+/// Fills `{template_stem}_{pvt}.lib` with the results of `corner` and writes
+/// `{out_stem}_{corner}.lib`.
+///
+/// The template has to be of the corner: its name has to carry the PVT and its
+/// nominal voltage and temperature, when given, have to match `PVT`.
+pub fn collect_corner(
+  corner: Corner,
+  infos: &[ArcInfo],
+  template_stem: &str,
+  out_stem: &str,
+  options: &CollectOptions,
+) -> anyhow::Result<CornerReport> {
+  let mut report = CornerReport {
+    corner,
+    lib: None,
+    missing_arcs: Vec::new(),
+    coverage: Vec::new(),
+  };
+  let template_path = corner.template(template_stem);
+  if !Path::new(&template_path).exists() {
+    return Ok(report);
+  }
+  let mut lib = read_lib(&template_path)?;
+  if !lib.name.contains(corner.pvt) {
+    bail!("{template_path}: library {} is not of corner {}", lib.name, corner.pvt);
+  }
+  let (_, _, voltage, temperature) = PVT
+    .iter()
+    .find(|(pvt, ..)| *pvt == corner.pvt)
+    .expect("corner of PVT");
+  for (attr, lib_value, pvt_value) in [
+    ("nom_voltage", lib.nom_voltage, *voltage),
+    ("nom_temperature", lib.nom_temperature, *temperature),
+  ] {
+    if let Some(lib_value) = lib_value {
+      if (lib_value - f64::from(pvt_value)).abs() > 1e-6 {
+        bail!("{template_path}: {attr} {lib_value} differs from {pvt_value} of {corner}");
+      }
+    }
+  }
+  for info in infos {
+    if !corner.arc_dir(info).exists() {
+      report.missing_arcs.push(missing_arc(info));
+      continue;
+    }
+    let run = Some(read_run(corner.run, corner.config_path(info.0))?);
+    let options = CollectOptions { corner, run, ..options.clone() };
+    report.coverage.extend(update_cell(*info, &options, &mut lib)?);
+  }
+  lib.name = format!("{}_{}", lib.name, corner.run);
+  let lib_path = format!("{out_stem}_{corner}.lib");
+  let mut writer = BufWriter::new(File::create(&lib_path)?);
+  write!(&mut writer, "{lib}")?;
+  report.lib = Some(lib_path);
+  Ok(report)
+}
+
+fn missing_arc(info: &ArcInfo) -> String {
+  format!("{} arc{}", info.1, info.4)
+}
+
+/// [`collect_corner`] for every corner, the ones without any result reported
+/// with all arcs missing
+pub fn collect_corners(
+  infos: &[ArcInfo],
+  template_stem: &str,
+  out_stem: &str,
+  options: &CollectOptions,
+) -> anyhow::Result<Vec<CornerReport>> {
+  let corners: Vec<Corner> = Corner::all().collect();
+  crate::parallel::install(|| {
+    corners
+      .par_iter()
+      .map(|corner| {
+        if corner.has_results(infos) {
+          collect_corner(*corner, infos, template_stem, out_stem, options)
+        } else {
+          Ok(CornerReport {
+            corner: *corner,
+            lib: None,
+            missing_arcs: infos.iter().map(missing_arc).collect(),
+            coverage: Vec::new(),
+          })
+        }
+      })
+      .collect()
+  })?
+}
+
+#[test]
+fn corner_paths() {
+  let corner = Corner { run: "10k_QMC", pvt: "ssg0p72vm40c" };
+  assert_eq!(corner.to_string(), "10k_QMC_ssg0p72vm40c");
+  assert_eq!(corner.template("pruned_100kMC"), "pruned_100kMC_ssg0p72vm40c.lib");
+  assert_eq!(corner.config_path("INV"), "../config/INV_10k_QMC_ssg0p72vm40c.yaml");
+  assert_eq!(Corner::all().count(), RUN.len() * PVT.len());
+  assert!(Corner::all().any(|c| c == Corner::default()));
+  let other_run = Corner { run: "100kMC", ..Default::default() };
+  assert_eq!(
+    other_run.moments_dir("INV"),
+    Path::new(MOMENTS_DIR).join("100kMC/INV/tt0p8v25c")
+  );
+  let report = CornerReport {
+    corner,
+    lib: None,
+    missing_arcs: Vec::new(),
+    coverage: Vec::new(),
+  };
+  assert!(!report.is_complete());
+  assert_eq!(report.to_string(), "10k_QMC_ssg0p72vm40c: no template");
+}
+
+#[test]
+fn corners_without_results() -> anyhow::Result<()> {
+  let infos = &crate::arcs::INFO[..2];
+  let reports = collect_corners(
+    infos,
+    "no_such_template",
+    "no_such_out",
+    &CollectOptions::default(),
+  )?;
+  assert_eq!(reports.len(), Corner::all().count());
+  for report in reports.iter().filter(|r| !r.corner.has_results(infos)) {
+    assert!(report.lib.is_none() && !report.is_complete());
+    assert_eq!(report.missing_arcs.len(), 2);
+    assert!(report.to_string().ends_with("no results, 2 arcs missing"));
+  }
+  Ok(())
+}
+
+#[test]
+fn collect_all_corners() -> anyhow::Result<()> {
+  let options = CollectOptions {
+    gaps: crate::fill::GapPolicy::Keep,
+    plausibility: crate::plausibility::PlausibilityOptions::from_env(),
+    ..Default::default()
+  };
+  let reports =
+    collect_corners(&crate::arcs::INFO, "pruned_100kMC", "pruned_active_lvf", &options)?;
+  for report in reports.iter().filter(|r| !r.is_complete()) {
+    println!("incomplete {report}");
+    for arc in report.missing_arcs.iter() {
+      println!("  {arc}");
+    }
+  }
+  let report_path = "pruned_active_lvf.corners.json";
+  serde_json::to_writer_pretty(BufWriter::new(File::create(report_path)?), &reports)?;
+  Ok(())
+}
@@ -0,0 +1,271 @@
+//! Structural diff of two libraries, for gating a library release.
+//!
+//! Cells, pins and timing groups are matched by their ids; matched timing
+//! tables are compared index by index and value by value, a value differing
+//! when it is off by more than both the absolute and the relative tolerance.
+use liberty_db::{timing::TimingTableLookUp, DefaultCtx, Library};
+use serde::Serialize;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Tolerance {
+  pub abs: f64,
+  pub rel: f64,
+}
+
+impl Default for Tolerance {
+  fn default() -> Self {
+    Self { abs: 1e-6, rel: 1e-6 }
+  }
+}
+
+impl Tolerance {
+  fn differ(&self, a: f64, b: f64) -> bool {
+    let d = (a - b).abs();
+    // NaN differs from everything but NaN
+    if d.is_nan() {
+      return a.is_nan() != b.is_nan();
+    }
+    d > self.abs && d > self.rel * b.abs()
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Change {
+  Added,
+  Removed,
+  IndexDiffers {
+    index: &'static str,
+    old: Vec<f64>,
+    new: Vec<f64>,
+  },
+  SizeDiffers {
+    field: &'static str,
+    old: usize,
+    new: usize,
+  },
+  /// row-major positions off by more than the tolerance and the largest difference
+  ValuesDiffer {
+    field: &'static str,
+    points: Vec<usize>,
+    max_abs: f64,
+  },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Difference {
+  pub path: String,
+  #[serde(flatten)]
+  pub change: Change,
+}
+
+impl fmt::Display for Difference {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}: ", self.path)?;
+    match &self.change {
+      Change::Added => write!(f, "added"),
+      Change::Removed => write!(f, "removed"),
+      Change::IndexDiffers { index, old, new } => write!(f, "{index} {old:?} -> {new:?}"),
+      Change::SizeDiffers { field, old, new } => write!(f, "{field} size {old} -> {new}"),
+      Change::ValuesDiffer { field, points, max_abs } => {
+        write!(f, "{field} differ at {points:?}, max {max_abs:e}")
+      }
+    }
+  }
+}
+
+fn diff_values(
+  path: &str,
+  field: &'static str,
+  old: &[f64],
+  new: &[f64],
+  tolerance: &Tolerance,
+  out: &mut Vec<Difference>,
+) {
+  let change = if old.len() != new.len() {
+    Change::SizeDiffers { field, old: old.len(), new: new.len() }
+  } else {
+    let points: Vec<usize> = (0..old.len())
+      .filter(|i| tolerance.differ(new[*i], old[*i]))
+      .collect();
+    if points.is_empty() {
+      return;
+    }
+    let max_abs = points.iter().map(|i| (new[*i] - old[*i]).abs()).fold(0.0, f64::max);
+    Change::ValuesDiffer { field, points, max_abs }
+  };
+  out.push(Difference { path: path.to_string(), change });
+}
+
+fn diff_table(
+  path: &str,
+  old: &TimingTableLookUp<DefaultCtx>,
+  new: &TimingTableLookUp<DefaultCtx>,
+  tolerance: &Tolerance,
+  out: &mut Vec<Difference>,
+) {
+  for (index, old_index, new_index) in [
+    ("index_1", &old.index_1, &new.index_1),
+    ("index_2", &old.index_2, &new.index_2),
+    ("lvf_index_1", &old.lvf_index_1, &new.lvf_index_1),
+    ("lvf_index_2", &old.lvf_index_2, &new.lvf_index_2),
+  ] {
+    let differ = old_index.len() != new_index.len()
+      || old_index
+        .iter()
+        .zip(new_index.iter())
+        .any(|(a, b)| tolerance.differ(*b, *a));
+    if differ {
+      let (old, new) = (old_index.clone(), new_index.clone());
+      let change = Change::IndexDiffers { index, old, new };
+      out.push(Difference { path: path.to_string(), change });
+    }
+  }
+  diff_values(path, "values", &old.values, &new.values, tolerance, out);
+  let lvf = |t: &TimingTableLookUp<DefaultCtx>, c: usize| -> Vec<f64> {
+    t.lvf_values
+      .iter()
+      .zip(t.values.iter())
+      .map(|(v, nominal)| [v.mean - nominal, v.std_dev, v.skewness][c])
+      .collect()
+  };
+  for (c, field) in ["ocv_mean_shift", "ocv_std_dev", "ocv_skewness"]
+    .into_iter()
+    .enumerate()
+  {
+    diff_values(path, field, &lvf(old, c), &lvf(new, c), tolerance, out);
+  }
+}
+
+/// Differences from `old` to `new`
+pub fn diff(
+  old: &Library<DefaultCtx>,
+  new: &Library<DefaultCtx>,
+  tolerance: &Tolerance,
+) -> Vec<Difference> {
+  let mut out = Vec::new();
+  let push = |out: &mut Vec<Difference>, path: String, change| {
+    out.push(Difference { path, change })
+  };
+  for cell in old.cell.iter().filter(|c| new.cell.get(&c.name).is_none()) {
+    push(&mut out, cell.name.clone(), Change::Removed);
+  }
+  for new_cell in new.cell.iter() {
+    let Some(old_cell) = old.cell.get(&new_cell.name) else {
+      push(&mut out, new_cell.name.clone(), Change::Added);
+      continue;
+    };
+    for pin in old_cell
+      .pin
+      .iter()
+      .filter(|p| new_cell.pin.get(p.name.as_ref()).is_none())
+    {
+      push(&mut out, format!("{}/{}", new_cell.name, pin.name), Change::Removed);
+    }
+    for new_pin in new_cell.pin.iter() {
+      let pin_path = format!("{}/{}", new_cell.name, new_pin.name);
+      let Some(old_pin) = old_cell.pin.get(new_pin.name.as_ref()) else {
+        push(&mut out, pin_path, Change::Added);
+        continue;
+      };
+      let pin_name = new_pin.name.to_string();
+      let timing_path = |t: &liberty_db::Timing<DefaultCtx>| {
+        crate::lint::timing_path(&new_cell.name, &pin_name, t)
+      };
+      let find = |pin: &liberty_db::Pin<DefaultCtx>,
+                  t: &liberty_db::Timing<DefaultCtx>| {
+        pin
+          .timing
+          .get(
+            t.related_pin.as_ref(),
+            t.timing_sense.as_ref(),
+            t.timing_type.as_ref(),
+            t.when.as_ref(),
+          )
+          .is_some()
+      };
+      for timing in old_pin.timing.iter().filter(|t| !find(new_pin, t)) {
+        push(&mut out, timing_path(timing), Change::Removed);
+      }
+      for new_timing in new_pin.timing.iter() {
+        let path = timing_path(new_timing);
+        let Some(old_timing) = old_pin.timing.get(
+          new_timing.related_pin.as_ref(),
+          new_timing.timing_sense.as_ref(),
+          new_timing.timing_type.as_ref(),
+          new_timing.when.as_ref(),
+        ) else {
+          push(&mut out, path, Change::Added);
+          continue;
+        };
+        for (name, old_table, new_table) in [
+          ("cell_rise", &old_timing.cell_rise, &new_timing.cell_rise),
+          ("cell_fall", &old_timing.cell_fall, &new_timing.cell_fall),
+          ("rise_transition", &old_timing.rise_transition, &new_timing.rise_transition),
+          ("fall_transition", &old_timing.fall_transition, &new_timing.fall_transition),
+          ("rise_constraint", &old_timing.rise_constraint, &new_timing.rise_constraint),
+          ("fall_constraint", &old_timing.fall_constraint, &new_timing.fall_constraint),
+          ("retaining_rise", &old_timing.retaining_rise, &new_timing.retaining_rise),
+          ("retaining_fall", &old_timing.retaining_fall, &new_timing.retaining_fall),
+          (
+            "retain_rise_slew",
+            &old_timing.retain_rise_slew,
+            &new_timing.retain_rise_slew,
+          ),
+          (
+            "retain_fall_slew",
+            &old_timing.retain_fall_slew,
+            &new_timing.retain_fall_slew,
+          ),
+        ] {
+          let table_path = format!("{path}/{name}");
+          match (old_table, new_table) {
+            (None, None) => {}
+            (Some(_), None) => push(&mut out, table_path, Change::Removed),
+            (None, Some(_)) => push(&mut out, table_path, Change::Added),
+            (Some(old_table), Some(new_table)) => {
+              diff_table(&table_path, old_table, new_table, tolerance, &mut out)
+            }
+          }
+        }
+      }
+    }
+  }
+  out
+}
+
+#[test]
+fn diff_tolerance() {
+  let tolerance = Tolerance { abs: 1e-3, rel: 1e-2 };
+  assert!(!tolerance.differ(1.0005, 1.0));
+  assert!(!tolerance.differ(1.005, 1.0));
+  assert!(tolerance.differ(1.02, 1.0));
+  assert!(tolerance.differ(f64::NAN, 1.0) && !tolerance.differ(f64::NAN, f64::NAN));
+  let mut out = Vec::new();
+  diff_values("A/Z", "values", &[1.0, 2.0, 3.0], &[1.0, 2.5, 3.0], &tolerance, &mut out);
+  assert_eq!(
+    out[0].change,
+    Change::ValuesDiffer { field: "values", points: vec![1], max_abs: 0.5 }
+  );
+  assert_eq!(
+    serde_json::to_string(&out[0]).unwrap(),
+    r#"{"path":"A/Z","kind":"values_differ","field":"values","points":[1],"max_abs":0.5}"#
+  );
+  let old = TimingTableLookUp::<DefaultCtx> {
+    index_1: vec![1.0, 2.0],
+    lvf_index_1: vec![1.0, 2.0],
+    ..Default::default()
+  };
+  let new = TimingTableLookUp { lvf_index_1: vec![1.0, 3.0], ..old.clone() };
+  let mut out = Vec::new();
+  diff_table("A/Z", &old, &new, &tolerance, &mut out);
+  assert_eq!(
+    out.iter().map(|d| &d.change).collect::<Vec<_>>(),
+    [&Change::IndexDiffers {
+      index: "lvf_index_1",
+      old: vec![1.0, 2.0],
+      new: vec![1.0, 3.0]
+    }]
+  );
+}
@@ -0,0 +1,250 @@
+//! Cell extraction from Liberty text without a full parse.
+//!
+//! The scanner streams the file once, tracking group depth outside of strings and
+//! comments. Every library-level statement except the `cell` groups that were not
+//! asked for is copied, so templates, units and operating conditions survive and
+//! the reduced text parses like the original.
+use anyhow::bail;
+use liberty_db::{DefaultCtx, Library};
+use std::{
+  collections::BTreeSet,
+  fs::File,
+  io::{BufRead, BufReader},
+  path::Path,
+};
+
+#[derive(Debug, Default)]
+struct Scanner {
+  depth: usize,
+  in_string: bool,
+  escaped: bool,
+  in_comment: bool,
+  prev: u8,
+  /// library-level statement not yet known to be kept
+  pending: Vec<u8>,
+  /// inside a library-level group that is dropped
+  skipping: bool,
+}
+
+/// Name of the group opened by `header`, `cell (NAME) ` or `cell ("NAME") `, when
+/// it is a cell
+fn cell_name(header: &[u8]) -> Option<String> {
+  let header = std::str::from_utf8(header).ok()?;
+  // drop comments in front of the group
+  let header = header.rsplit("*/").next()?.trim();
+  let rest = header.strip_prefix("cell")?.trim_start();
+  let name = rest.strip_prefix('(')?.strip_suffix(')')?.trim();
+  Some(name.trim_matches('"').to_string())
+}
+
+/// Where a line of `header` starts a new statement, as `(end of the text before
+/// it, start of the line)`, the whole of it first. An attribute may end at the line
+/// break instead of a `;`, so a group header can follow it in one pending statement.
+fn line_starts(header: &[u8]) -> Vec<(usize, usize)> {
+  let mut starts = vec![(0, 0)];
+  let (mut content_end, mut newline) = (0, false);
+  let mut i = 0;
+  while i < header.len() {
+    match header[i] {
+      b'/' if header.get(i + 1) == Some(&b'*') => {
+        let close = header[i + 2..].windows(2).position(|w| w == b"*/");
+        i = close.map_or(header.len(), |p| i + p + 4);
+        continue;
+      }
+      b'\n' => newline = true,
+      b if b.is_ascii_whitespace() => {}
+      b => {
+        if newline {
+          starts.push((content_end, i));
+          newline = false;
+        }
+        if b == b'"' {
+          i += 1;
+          while i < header.len() && header[i] != b'"' {
+            i += if header[i] == b'\\' { 2 } else { 1 };
+          }
+        }
+        content_end = (i + 1).min(header.len());
+      }
+    }
+    i += 1;
+  }
+  starts
+}
+
+impl Scanner {
+  fn feed(
+    &mut self,
+    chunk: &[u8],
+    cells: &BTreeSet<String>,
+    found: &mut BTreeSet<String>,
+    out: &mut Vec<u8>,
+  ) {
+    for &b in chunk {
+      let prev = std::mem::replace(&mut self.prev, b);
+      let structural = if self.in_comment {
+        if prev == b'*' && b == b'/' {
+          self.in_comment = false;
+          // `*/*` does not open another comment
+          self.prev = 0;
+        }
+        false
+      } else if self.in_string {
+        if self.escaped {
+          self.escaped = false;
+        } else if b == b'\\' {
+          self.escaped = true;
+        } else if b == b'"' {
+          self.in_string = false;
+        }
+        false
+      } else if prev == b'/' && b == b'*' {
+        self.in_comment = true;
+        self.prev = 0;
+        false
+      } else if b == b'"' {
+        self.in_string = true;
+        false
+      } else {
+        true
+      };
+      if self.skipping {
+        if structural && b == b'{' {
+          self.depth += 1;
+        } else if structural && b == b'}' {
+          self.depth -= 1;
+          if self.depth == 1 {
+            self.skipping = false;
+          }
+        }
+        continue;
+      }
+      if self.depth == 1 {
+        self.pending.push(b);
+      } else {
+        out.push(b);
+      }
+      if !structural {
+        continue;
+      }
+      match b {
+        b'{' => {
+          if self.depth == 1 {
+            let header = &self.pending[..self.pending.len() - 1];
+            let cell = line_starts(header).into_iter().rev().find_map(|(end, start)| {
+              cell_name(&header[start..]).map(|name| (end, name))
+            });
+            match cell {
+              Some((end, name)) if !cells.contains(&name) => {
+                out.extend_from_slice(&self.pending[..end]);
+                self.pending.clear();
+                self.skipping = true;
+              }
+              cell => {
+                found.extend(cell.map(|(_, name)| name));
+                out.append(&mut self.pending);
+              }
+            }
+          }
+          self.depth += 1;
+        }
+        b'}' => {
+          self.depth = self.depth.saturating_sub(1);
+          if self.depth == 0 {
+            // the closing brace of the library went to `pending`
+            out.append(&mut self.pending);
+          }
+        }
+        b';' if self.depth == 1 => out.append(&mut self.pending),
+        _ => {}
+      }
+    }
+  }
+}
+
+/// The library header and the groups of `cells` out of Liberty text, failing when
+/// a requested cell is not in it
+pub fn extract_cells(
+  mut reader: impl BufRead,
+  cells: impl IntoIterator<Item = impl AsRef<str>>,
+) -> anyhow::Result<String> {
+  let cells: BTreeSet<String> =
+    cells.into_iter().map(|c| c.as_ref().to_string()).collect();
+  let mut scanner = Scanner::default();
+  let mut found = BTreeSet::new();
+  let mut out = Vec::new();
+  loop {
+    let chunk = reader.fill_buf()?;
+    if chunk.is_empty() {
+      break;
+    }
+    let len = chunk.len();
+    scanner.feed(chunk, &cells, &mut found, &mut out);
+    reader.consume(len);
+  }
+  out.append(&mut scanner.pending);
+  let missing: Vec<&String> = cells.difference(&found).collect();
+  if !missing.is_empty() {
+    bail!("cells not found: {missing:?}");
+  }
+  Ok(String::from_utf8(out)?)
+}
+
+/// `path` parsed with only `cells` in it
+pub fn read_cells(
+  path: impl AsRef<Path>,
+  cells: impl IntoIterator<Item = impl AsRef<str>>,
+) -> anyhow::Result<Library<DefaultCtx>> {
+  let path = path.as_ref();
+  let file =
+    File::open(path).map_err(|e| anyhow::anyhow!("read {}: {e}", path.display()))?;
+  let text = extract_cells(BufReader::with_capacity(1 << 20, file), cells)
+    .map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))?;
+  Library::parse_lib(&text)
+    .map_err(|e| anyhow::anyhow!("parse {}: {e:?}", path.display()))
+}
+
+#[test]
+fn extract_cell_blocks() -> anyhow::Result<()> {
+  let text = r#"library (small) {
+  time_unit : "1ns";
+  /* cell (COMMENTED) { } */
+  cell (INV) {
+    area : 1;
+    pin (ZN) {
+      function : "!I";
+    }
+  }
+  cell ("BUF") {
+    area : 2; /* } */
+    pin (Z) {
+      function : "{I}";
+    }
+  }
+  cell (AN2) {
+    area : 3;
+  }
+  default_max_transition : 0.5
+  /* OR2 comment */
+  cell (OR2) {
+    area : 4;
+  }
+}
+"#;
+  // tiny chunks to cross every state at a chunk boundary
+  let reduced = extract_cells(BufReader::with_capacity(3, text.as_bytes()), ["BUF"])?;
+  assert!(reduced.contains("cell (\"BUF\")") && reduced.contains("area : 2;"));
+  assert!(!reduced.contains("INV") && !reduced.contains("AN2"));
+  // comments in front of a dropped cell go with it
+  assert!(reduced.contains("time_unit") && !reduced.contains("COMMENTED"));
+  // an attribute without `;` is a statement of its own
+  assert!(reduced.contains("default_max_transition : 0.5\n}"));
+  assert!(!reduced.contains("OR2") && !reduced.contains("area : 4"));
+  let lib =
+    Library::<DefaultCtx>::parse_lib(&reduced).map_err(|e| anyhow::anyhow!("{e:?}"))?;
+  assert_eq!(lib.cell.iter().map(|c| c.name.as_str()).collect::<Vec<_>>(), ["BUF"]);
+  assert!(extract_cells(text.as_bytes(), ["INV", "OR3"]).is_err());
+  let reduced = extract_cells(text.as_bytes(), ["OR2"])?;
+  assert!(reduced.contains("cell (OR2)") && reduced.contains("OR2 comment"));
+  Ok(())
+}
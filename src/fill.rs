@@ -0,0 +1,266 @@
+//! Filling the table points that have no simulation result.
+//!
+//! [`GapPolicy::Bilinear`] interpolates a missing point from the four known
+//! corners of the tightest rectangle around it, extrapolating when no rectangle
+//! encloses it. [`GapPolicy::Spline`], and bilinear without any rectangle of known
+//! points, estimate it along `index_1` from the known points of its column and
+//! along `index_2` from the known points of its row; the two estimates are
+//! averaged, or the only available one is used.
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum GapPolicy {
+  /// refuse to write a table with missing points
+  #[default]
+  Fail,
+  /// keep the template value of missing points
+  Keep,
+  /// from the four known points around a missing one
+  Bilinear,
+  /// natural cubic spline along each axis
+  Spline,
+}
+
+impl fmt::Display for GapPolicy {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(match self {
+      GapPolicy::Fail => "fail",
+      GapPolicy::Keep => "keep",
+      GapPolicy::Bilinear => "bilinear",
+      GapPolicy::Spline => "spline",
+    })
+  }
+}
+
+impl FromStr for GapPolicy {
+  type Err = anyhow::Error;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "fail" => Ok(GapPolicy::Fail),
+      "keep" => Ok(GapPolicy::Keep),
+      "bilinear" => Ok(GapPolicy::Bilinear),
+      "spline" => Ok(GapPolicy::Spline),
+      _ => bail!("unknown gap policy {s:?}"),
+    }
+  }
+}
+
+/// Linear interpolation between the known points around `x`,
+/// extrapolation from the two nearest ones outside them.
+fn linear(xs: &[f64], ys: &[f64], x: f64) -> f64 {
+  let hi = xs.partition_point(|v| *v < x).clamp(1, xs.len() - 1);
+  let (x0, x1, y0, y1) = (xs[hi - 1], xs[hi], ys[hi - 1], ys[hi]);
+  y0 + (y1 - y0) * (x - x0) / (x1 - x0)
+}
+
+/// Natural cubic spline through the known points, linear outside them.
+fn spline(xs: &[f64], ys: &[f64], x: f64) -> f64 {
+  let n = xs.len();
+  if n < 3 || x <= xs[0] || x >= xs[n - 1] {
+    return linear(xs, ys, x);
+  }
+  let h: Vec<f64> = xs.windows(2).map(|w| w[1] - w[0]).collect();
+  // second derivatives m[1..n-1] from the tridiagonal system, m[0] = m[n-1] = 0
+  let mut diag = vec![0.0; n];
+  let mut rhs = vec![0.0; n];
+  for i in 1..n - 1 {
+    diag[i] = 2.0 * (h[i - 1] + h[i]);
+    rhs[i] = 6.0 * ((ys[i + 1] - ys[i]) / h[i] - (ys[i] - ys[i - 1]) / h[i - 1]);
+  }
+  for i in 2..n - 1 {
+    let w = h[i - 1] / diag[i - 1];
+    diag[i] -= w * h[i - 1];
+    rhs[i] -= w * rhs[i - 1];
+  }
+  let mut m = vec![0.0; n];
+  for i in (1..n - 1).rev() {
+    m[i] = (rhs[i] - h[i] * m[i + 1]) / diag[i];
+  }
+  let k = xs.partition_point(|v| *v < x).clamp(1, n - 1) - 1;
+  let (a, b) = ((xs[k + 1] - x) / h[k], (x - xs[k]) / h[k]);
+  a * ys[k]
+    + b * ys[k + 1]
+    + ((a * a * a - a) * m[k] + (b * b * b - b) * m[k + 1]) * h[k] * h[k] / 6.0
+}
+
+/// Estimate at `x` from the known `(xs, ys)` of one axis, `None` below two points
+fn along(policy: GapPolicy, xs: &[f64], ys: &[f64], x: f64) -> Option<f64> {
+  if xs.len() < 2 {
+    return None;
+  }
+  match policy {
+    GapPolicy::Spline => Some(spline(xs, ys, x)),
+    _ => Some(linear(xs, ys, x)),
+  }
+}
+
+/// Bilinear estimate at `(i1, i2)` from the known corners of the rectangle that
+/// encloses it most tightly, or failing that lies closest to it
+fn bilinear(
+  index_1: &[f64],
+  index_2: &[f64],
+  known: &[Option<f64>],
+  (i1, i2): (usize, usize),
+) -> Option<f64> {
+  let size2 = index_2.len();
+  let pairs = |n: usize, i: usize| {
+    (0..n)
+      .flat_map(move |a| (a + 1..n).map(move |b| (a, b)))
+      .map(move |(a, b)| {
+        let encloses = a <= i && i <= b;
+        ((!encloses, i.abs_diff(a).max(i.abs_diff(b))), (a, b))
+      })
+  };
+  let ((a1, b1), (a2, b2), corners) = pairs(index_1.len(), i1)
+    .flat_map(|(cost_1, rows)| {
+      pairs(size2, i2).map(move |(cost_2, columns)| {
+        ((cost_1.0 || cost_2.0, cost_1.1 + cost_2.1), rows, columns)
+      })
+    })
+    .filter_map(|(cost, (a1, b1), (a2, b2))| {
+      let corner = |r: usize, c: usize| known[r * size2 + c];
+      let corners = [corner(a1, a2)?, corner(a1, b2)?, corner(b1, a2)?, corner(b1, b2)?];
+      Some((cost, (a1, b1), (a2, b2), corners))
+    })
+    .min_by_key(|(cost, ..)| *cost)
+    .map(|(_, rows, columns, corners)| (rows, columns, corners))?;
+  let t = (index_1[i1] - index_1[a1]) / (index_1[b1] - index_1[a1]);
+  let u = (index_2[i2] - index_2[a2]) / (index_2[b2] - index_2[a2]);
+  let [f00, f01, f10, f11] = corners;
+  Some(
+    (1.0 - t) * (1.0 - u) * f00 + (1.0 - t) * u * f01 + t * (1.0 - u) * f10 + t * u * f11,
+  )
+}
+
+/// Fills the `None` points of a row-major `index_1` x `index_2` grid.
+/// 1-D tables pass an empty `index_2`.
+pub fn fill(
+  policy: GapPolicy,
+  index_1: &[f64],
+  index_2: &[f64],
+  grid: &mut [Option<f64>],
+) -> anyhow::Result<()> {
+  let size2 = index_2.len().max(1);
+  let missing: Vec<usize> = (0..grid.len()).filter(|p| grid[*p].is_none()).collect();
+  match policy {
+    GapPolicy::Fail if !missing.is_empty() => bail!("points {missing:?} are missing"),
+    GapPolicy::Fail | GapPolicy::Keep => return Ok(()),
+    GapPolicy::Bilinear | GapPolicy::Spline => {}
+  }
+  let known = grid.to_vec();
+  for p in missing {
+    let (i1, i2) = (p / size2, p % size2);
+    if policy == GapPolicy::Bilinear && !index_2.is_empty() {
+      if let Some(value) = bilinear(index_1, index_2, &known, (i1, i2)) {
+        grid[p] = Some(value);
+        continue;
+      }
+    }
+    let (mut xs, mut ys) = (Vec::new(), Vec::new());
+    for (k, x) in index_1.iter().enumerate() {
+      if let Some(y) = known[k * size2 + i2] {
+        xs.push(*x);
+        ys.push(y);
+      }
+    }
+    let by_1 = along(policy, &xs, &ys, index_1[i1]);
+    let (mut xs, mut ys) = (Vec::new(), Vec::new());
+    for (k, x) in index_2.iter().enumerate() {
+      if let Some(y) = known[i1 * size2 + k] {
+        xs.push(*x);
+        ys.push(y);
+      }
+    }
+    let by_2 = index_2.get(i2).and_then(|x| along(policy, &xs, &ys, *x));
+    grid[p] = match (by_1, by_2) {
+      (Some(a), Some(b)) => Some((a + b) / 2.0),
+      (Some(a), None) | (None, Some(a)) => Some(a),
+      (None, None) => bail!("point {p} has too few known neighbours to {policy}"),
+    };
+  }
+  Ok(())
+}
+
+/// Bilinear resampling of a row-major `from_1` x `from_2` grid onto `to_1` x
+/// `to_2`, extrapolating linearly outside the source grid. 1-D tables pass empty
+/// `from_2` and `to_2`.
+pub fn resample(
+  from_1: &[f64],
+  from_2: &[f64],
+  values: &[f64],
+  to_1: &[f64],
+  to_2: &[f64],
+) -> Vec<f64> {
+  let along_2 = |row: &[f64], x2: Option<&f64>| match x2 {
+    Some(x2) if from_2.len() >= 2 => linear(from_2, row, *x2),
+    _ => row[0],
+  };
+  let size2 = from_2.len().max(1);
+  let mut out = Vec::with_capacity(to_1.len() * to_2.len().max(1));
+  for x1 in to_1 {
+    for x2 in to_2.iter().map(Some).chain(to_2.is_empty().then_some(None)) {
+      let column: Vec<f64> = values.chunks(size2).map(|row| along_2(row, x2)).collect();
+      out.push(if from_1.len() >= 2 { linear(from_1, &column, *x1) } else { column[0] });
+    }
+  }
+  out
+}
+
+#[test]
+fn fill_gaps() -> anyhow::Result<()> {
+  // z = x + 10 * y is reproduced exactly by both interpolations
+  let (index_1, index_2) = ([1.0, 2.0, 4.0], [0.0, 1.0, 3.0]);
+  let exact: Vec<f64> = index_1
+    .iter()
+    .flat_map(|x| index_2.iter().map(move |y| x + 10.0 * y))
+    .collect();
+  for policy in [GapPolicy::Bilinear, GapPolicy::Spline] {
+    let mut grid: Vec<Option<f64>> = exact.iter().copied().map(Some).collect();
+    grid[4] = None;
+    grid[8] = None;
+    fill(policy, &index_1, &index_2, &mut grid)?;
+    for (got, want) in grid.iter().zip(exact.iter()) {
+      assert!((got.unwrap() - want).abs() < 1e-9, "{policy}: {got:?} != {want}");
+    }
+  }
+  // z = x^2 + y^2 is not bilinear; its row and column leave the centre only one
+  // known point each, but the corners of the table surround it
+  let index = [0.0, 1.0, 2.0];
+  let mut grid: Vec<Option<f64>> = index
+    .iter()
+    .flat_map(|x| index.iter().map(move |y| Some(x * x + y * y)))
+    .collect();
+  for p in [1, 3, 4] {
+    grid[p] = None;
+  }
+  assert!(fill(GapPolicy::Spline, &index, &index, &mut grid.clone()).is_err());
+  fill(GapPolicy::Bilinear, &index, &index, &mut grid)?;
+  assert_eq!((grid[1], grid[3], grid[4]), (Some(2.0), Some(2.0), Some(4.0)));
+  let mut grid = vec![Some(1.0), None, Some(3.0)];
+  assert!(fill(GapPolicy::Fail, &index_1, &[], &mut grid).is_err());
+  fill(GapPolicy::Bilinear, &[1.0, 2.0, 3.0], &[], &mut grid)?;
+  assert_eq!(grid[1], Some(2.0));
+  Ok(())
+}
+
+#[test]
+fn resample_grid() {
+  // bilinear surfaces come back exactly, also when extrapolated
+  let (from_1, from_2) = ([1.0, 2.0, 4.0], [0.0, 1.0]);
+  let values: Vec<f64> = from_1
+    .iter()
+    .flat_map(|x| from_2.iter().map(move |y| x + 10.0 * y + x * y))
+    .collect();
+  let (to_1, to_2) = ([1.5, 5.0], [0.5, 2.0]);
+  let got = resample(&from_1, &from_2, &values, &to_1, &to_2);
+  let want: Vec<f64> = to_1
+    .iter()
+    .flat_map(|x| to_2.iter().map(move |y| x + 10.0 * y + x * y))
+    .collect();
+  for (got, want) in got.iter().zip(want.iter()) {
+    assert!((got - want).abs() < 1e-12, "{got} != {want}");
+  }
+  assert_eq!(resample(&[1.0, 3.0], &[], &[2.0, 6.0], &[2.0], &[]), [4.0]);
+}
@@ -0,0 +1,380 @@
+//! Stage graph of the characterization flow.
+//!
+//! Every stage declares the files it reads and writes, the stages it depends on and
+//! the command that produces its outputs. The command line and the input
+//! fingerprints of the last successful run are kept in a state file, so
+//! [`Flow::run`] only re-executes the stages whose command or inputs changed, whose
+//! outputs are missing, or whose upstream stages re-ran.
+use anyhow::{bail, Context as _};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+  collections::{BTreeMap, HashMap, HashSet},
+  fmt,
+  fs::{self, File},
+  io::{self, BufReader, BufWriter},
+  path::{Path, PathBuf},
+  process::Command,
+  time::UNIX_EPOCH,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fingerprint {
+  pub size: u64,
+  pub mtime: (u64, u32),
+  pub sha256: String,
+}
+
+impl Fingerprint {
+  /// Reuses `known` when size and mtime did not move, otherwise hashes the content.
+  pub fn of(path: &Path, known: Option<&Fingerprint>) -> anyhow::Result<Self> {
+    let meta = fs::metadata(path).with_context(|| format!("{}", path.display()))?;
+    let mtime = meta.modified()?.duration_since(UNIX_EPOCH)?;
+    let (size, mtime) = (meta.len(), (mtime.as_secs(), mtime.subsec_nanos()));
+    if let Some(known) = known {
+      if known.size == size && known.mtime == mtime {
+        return Ok(known.clone());
+      }
+    }
+    let mut hasher = Sha256::new();
+    io::copy(&mut BufReader::new(File::open(path)?), &mut hasher)?;
+    let sha256 = hasher.finalize().iter().map(|b| format!("{b:02x}")).collect();
+    Ok(Self { size, mtime, sha256 })
+  }
+}
+
+/// All regular files below `path`, or `path` itself when it is a file.
+pub fn expand(path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+  if !path.is_dir() {
+    return Ok(vec![path.to_path_buf()]);
+  }
+  let mut files = Vec::new();
+  for entry in fs::read_dir(path)? {
+    files.extend(expand(&entry?.path())?);
+  }
+  files.sort();
+  Ok(files)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Stage {
+  pub name: &'static str,
+  pub deps: Vec<&'static str>,
+  pub inputs: Vec<PathBuf>,
+  pub outputs: Vec<PathBuf>,
+  /// program followed by its arguments
+  pub command: Vec<String>,
+  pub dir: Option<PathBuf>,
+}
+
+impl Stage {
+  pub fn new(name: &'static str) -> Self {
+    Self { name, ..Default::default() }
+  }
+  pub fn after(mut self, deps: &[&'static str]) -> Self {
+    self.deps.extend_from_slice(deps);
+    self
+  }
+  pub fn input(mut self, path: impl Into<PathBuf>) -> Self {
+    self.inputs.push(path.into());
+    self
+  }
+  pub fn output(mut self, path: impl Into<PathBuf>) -> Self {
+    self.outputs.push(path.into());
+    self
+  }
+  pub fn command<S: ToString>(mut self, args: &[S]) -> Self {
+    self.command = args.iter().map(ToString::to_string).collect();
+    self
+  }
+  pub fn dir(mut self, dir: impl Into<PathBuf>) -> Self {
+    self.dir = Some(dir.into());
+    self
+  }
+  fn fingerprints(
+    &self,
+    known: Option<&BTreeMap<PathBuf, Fingerprint>>,
+  ) -> anyhow::Result<BTreeMap<PathBuf, Fingerprint>> {
+    let mut fingerprints = BTreeMap::new();
+    for input in self.inputs.iter() {
+      for file in expand(input)? {
+        let fingerprint = Fingerprint::of(&file, known.and_then(|k| k.get(&file)))?;
+        fingerprints.insert(file, fingerprint);
+      }
+    }
+    Ok(fingerprints)
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reason {
+  NeverRun,
+  CommandChanged,
+  InputMissing(PathBuf),
+  InputChanged(PathBuf),
+  InputRemoved(PathBuf),
+  OutputMissing(PathBuf),
+  Upstream(&'static str),
+}
+
+impl fmt::Display for Reason {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Reason::NeverRun => write!(f, "never run"),
+      Reason::CommandChanged => write!(f, "command changed"),
+      Reason::InputMissing(p) => write!(f, "input {} missing", p.display()),
+      Reason::InputChanged(p) => write!(f, "input {} changed", p.display()),
+      Reason::InputRemoved(p) => write!(f, "input {} removed", p.display()),
+      Reason::OutputMissing(p) => write!(f, "output {} missing", p.display()),
+      Reason::Upstream(s) => write!(f, "upstream stage {s} re-runs"),
+    }
+  }
+}
+
+/// What a stage last ran successfully with
+#[derive(Debug, Serialize, Deserialize)]
+struct LastRun {
+  command: Vec<String>,
+  dir: Option<PathBuf>,
+  inputs: BTreeMap<PathBuf, Fingerprint>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct State(BTreeMap<String, LastRun>);
+
+pub struct Flow {
+  stages: Vec<Stage>,
+  state_path: PathBuf,
+}
+
+impl Flow {
+  pub fn new(state_path: impl Into<PathBuf>) -> Self {
+    Self { stages: Vec::new(), state_path: state_path.into() }
+  }
+  pub fn stage(mut self, stage: Stage) -> Self {
+    self.stages.push(stage);
+    self
+  }
+  pub fn stages(&self) -> &[Stage] {
+    &self.stages
+  }
+  /// Topological order of `targets` and everything they depend on,
+  /// or of the whole graph when `targets` is empty.
+  pub fn order(&self, targets: &[&str]) -> anyhow::Result<Vec<&Stage>> {
+    let by_name: HashMap<&str, &Stage> =
+      self.stages.iter().map(|s| (s.name, s)).collect();
+    if by_name.len() != self.stages.len() {
+      bail!("duplicated stage name");
+    }
+    fn visit<'a>(
+      name: &str,
+      by_name: &HashMap<&str, &'a Stage>,
+      visiting: &mut Vec<&'a str>,
+      done: &mut HashSet<&'a str>,
+      order: &mut Vec<&'a Stage>,
+    ) -> anyhow::Result<()> {
+      let stage = *by_name.get(name).with_context(|| format!("unknown stage {name}"))?;
+      if done.contains(stage.name) {
+        return Ok(());
+      }
+      if visiting.contains(&stage.name) {
+        bail!("dependency cycle: {} -> {}", visiting.join(" -> "), stage.name);
+      }
+      visiting.push(stage.name);
+      for dep in stage.deps.iter() {
+        visit(dep, by_name, visiting, done, order)?;
+      }
+      visiting.pop();
+      done.insert(stage.name);
+      order.push(stage);
+      Ok(())
+    }
+    let (mut visiting, mut done, mut order) = (Vec::new(), HashSet::new(), Vec::new());
+    if targets.is_empty() {
+      for stage in self.stages.iter() {
+        visit(stage.name, &by_name, &mut visiting, &mut done, &mut order)?;
+      }
+    } else {
+      for target in targets {
+        visit(target, &by_name, &mut visiting, &mut done, &mut order)?;
+      }
+    }
+    Ok(order)
+  }
+  fn load_state(&self) -> anyhow::Result<State> {
+    if self.state_path.exists() {
+      serde_json::from_reader(BufReader::new(File::open(&self.state_path)?))
+        .with_context(|| format!("{}: unreadable flow state", self.state_path.display()))
+    } else {
+      Ok(State::default())
+    }
+  }
+  fn save_state(&self, state: &State) -> anyhow::Result<()> {
+    let writer = BufWriter::new(File::create(&self.state_path)?);
+    serde_json::to_writer_pretty(writer, state)?;
+    Ok(())
+  }
+  fn reason(
+    stage: &Stage,
+    state: &State,
+    rerun: &HashSet<&str>,
+  ) -> anyhow::Result<Option<Reason>> {
+    if let Some(dep) = stage.deps.iter().find(|dep| rerun.contains(**dep)) {
+      return Ok(Some(Reason::Upstream(dep)));
+    }
+    if let Some(input) = stage.inputs.iter().find(|p| !p.exists()) {
+      return Ok(Some(Reason::InputMissing(input.clone())));
+    }
+    if let Some(output) = stage.outputs.iter().find(|p| !p.exists()) {
+      return Ok(Some(Reason::OutputMissing(output.clone())));
+    }
+    let Some(last) = state.0.get(stage.name) else {
+      return Ok(Some(Reason::NeverRun));
+    };
+    if last.command != stage.command || last.dir != stage.dir {
+      return Ok(Some(Reason::CommandChanged));
+    }
+    let known = &last.inputs;
+    let current = stage.fingerprints(Some(known))?;
+    if let Some((path, _)) = current.iter().find(|(p, f)| known.get(*p) != Some(*f)) {
+      return Ok(Some(Reason::InputChanged(path.clone())));
+    }
+    if let Some(path) = known.keys().find(|p| !current.contains_key(*p)) {
+      return Ok(Some(Reason::InputRemoved(path.clone())));
+    }
+    Ok(None)
+  }
+  /// Every stage of `targets` in execution order, paired with the reason it has to
+  /// run, `None` when it is up to date.
+  pub fn plan(&self, targets: &[&str]) -> anyhow::Result<Vec<(&Stage, Option<Reason>)>> {
+    let state = self.load_state()?;
+    let mut rerun = HashSet::new();
+    let mut plan = Vec::new();
+    for stage in self.order(targets)? {
+      let reason = Self::reason(stage, &state, &rerun)?;
+      if reason.is_some() {
+        rerun.insert(stage.name);
+      }
+      plan.push((stage, reason));
+    }
+    Ok(plan)
+  }
+  /// Runs the out-of-date stages of `targets`, or only prints the plan when `dry_run`.
+  pub fn run(&self, targets: &[&str], dry_run: bool) -> anyhow::Result<()> {
+    if dry_run {
+      for (stage, reason) in self.plan(targets)? {
+        match reason {
+          Some(reason) => {
+            println!(
+              "[run ] {}: {reason}\n       {}",
+              stage.name,
+              stage.command.join(" ")
+            )
+          }
+          None => println!("[skip] {}", stage.name),
+        }
+      }
+      return Ok(());
+    }
+    let mut state = self.load_state()?;
+    let mut rerun = HashSet::new();
+    for stage in self.order(targets)? {
+      let Some(reason) = Self::reason(stage, &state, &rerun)? else {
+        println!("[skip] {}", stage.name);
+        continue;
+      };
+      println!("[run ] {}: {reason}", stage.name);
+      let (program, args) = stage
+        .command
+        .split_first()
+        .with_context(|| format!("{} has no command", stage.name))?;
+      let mut command = Command::new(program);
+      command.args(args);
+      if let Some(dir) = &stage.dir {
+        command.current_dir(dir);
+      }
+      let status = command
+        .status()
+        .with_context(|| format!("{}: {program}", stage.name))?;
+      if !status.success() {
+        bail!("stage {} failed with {status}", stage.name);
+      }
+      if let Some(output) = stage.outputs.iter().find(|p| !p.exists()) {
+        bail!("stage {} did not produce {}", stage.name, output.display());
+      }
+      let known = state.0.get(stage.name).map(|last| &last.inputs);
+      let last = LastRun {
+        command: stage.command.clone(),
+        dir: stage.dir.clone(),
+        inputs: stage.fingerprints(known)?,
+      };
+      state.0.insert(stage.name.to_string(), last);
+      self.save_state(&state)?;
+      rerun.insert(stage.name);
+    }
+    Ok(())
+  }
+}
+
+#[test]
+fn flow_order() -> anyhow::Result<()> {
+  let flow = Flow::new("unused.json")
+    .stage(Stage::new("db").after(&["merge", "prune"]))
+    .stage(Stage::new("merge").after(&["prune"]))
+    .stage(Stage::new("prune"))
+    .stage(Stage::new("other"));
+  let names = |order: Vec<&Stage>| order.iter().map(|s| s.name).collect::<Vec<_>>();
+  assert_eq!(names(flow.order(&["db"])?), ["prune", "merge", "db"]);
+  assert_eq!(names(flow.order(&[])?), ["prune", "merge", "db", "other"]);
+  let cyclic = Flow::new("unused.json")
+    .stage(Stage::new("a").after(&["b"]))
+    .stage(Stage::new("b").after(&["a"]));
+  assert!(cyclic.order(&[]).is_err());
+  assert!(flow.order(&["missing"]).is_err());
+  Ok(())
+}
+
+#[test]
+fn flow_incremental() -> anyhow::Result<()> {
+  let dir = std::env::temp_dir().join(format!("flow_incremental_{}", std::process::id()));
+  fs::create_dir_all(&dir)?;
+  fs::write(dir.join("a.txt"), "a")?;
+  let flow = Flow::new(dir.join("state.json"))
+    .stage(
+      Stage::new("copy")
+        .input(dir.join("a.txt"))
+        .output(dir.join("b.txt"))
+        .command(&["cp", "a.txt", "b.txt"])
+        .dir(&dir),
+    )
+    .stage(
+      Stage::new("concat")
+        .after(&["copy"])
+        .input(dir.join("b.txt"))
+        .output(dir.join("c.txt"))
+        .command(&["sh", "-c", "cat b.txt b.txt > c.txt"])
+        .dir(&dir),
+    );
+  let reasons = |flow: &Flow| -> anyhow::Result<Vec<Option<Reason>>> {
+    Ok(flow.plan(&[])?.into_iter().map(|(_, r)| r).collect())
+  };
+  assert_eq!(
+    reasons(&flow)?,
+    [Some(Reason::OutputMissing(dir.join("b.txt"))), Some(Reason::Upstream("copy"))]
+  );
+  flow.run(&[], false)?;
+  assert_eq!(fs::read_to_string(dir.join("c.txt"))?, "aa");
+  assert_eq!(reasons(&flow)?, [None, None]);
+  fs::write(dir.join("a.txt"), "xyz")?;
+  assert_eq!(
+    reasons(&flow)?,
+    [Some(Reason::InputChanged(dir.join("a.txt"))), Some(Reason::Upstream("copy"))]
+  );
+  flow.run(&[], false)?;
+  assert_eq!(fs::read_to_string(dir.join("c.txt"))?, "xyzxyz");
+  let mut flow = flow;
+  flow.stages[1].command = ["sh", "-c", "cat b.txt > c.txt"].map(String::from).to_vec();
+  assert_eq!(reasons(&flow)?, [None, Some(Reason::CommandChanged)]);
+  fs::remove_dir_all(&dir)?;
+  Ok(())
+}
@@ -1,8 +1,30 @@
-// mod arcs;
+pub mod aocv;
+pub mod arcs;
+pub mod cache;
+pub mod compare;
+pub mod confidence;
+pub mod corner;
+pub mod diff;
+pub mod extract;
+pub mod fill;
+pub mod flow;
+pub mod lint;
+pub mod lvf_lint;
+pub mod merge;
+pub mod moments;
+pub mod parallel;
+pub mod plausibility;
+pub mod pocv;
+pub mod predict;
+pub mod provenance;
+pub mod sigma;
+pub mod smooth;
+pub mod stats;
+pub mod trend;
 use liberty_db::{
   ast::GroupSet,
   cell::{self, Cell},
-  DefaultCtx,
+  DefaultCtx, Library,
 };
 use serde::{Deserialize, Serialize};
 use std::{
@@ -191,6 +213,34 @@ pub const CELL_GROUP: &[(&str, (&str, &str, &str, bool), &[&str])] = &[
   ),
 ];
 
+/// `CELL_GROUP` family of `cell`
+pub fn family_of(cell: &str) -> Option<&'static str> {
+  CELL_GROUP
+    .iter()
+    .find(|(_, _, cells)| cells.contains(&cell))
+    .map(|(name, _, _)| *name)
+}
+
+/// Drive strength of a `CELL_GROUP` cell, 0.7 for `INVD0P7BWP30P140`
+pub fn drive_of(cell: &str) -> Option<f64> {
+  let rest = cell.strip_prefix(family_of(cell)?)?.strip_prefix('D')?;
+  let (drive, _) = rest.split_once("BWP")?;
+  drive.replace('P', ".").parse().ok()
+}
+
+/// `PVT` corner named in `lib_name`, as in `tcbn22ullbwp30p140tt0p8v25c`
+pub fn pvt_of(lib_name: &str) -> Option<&'static str> {
+  PVT
+    .iter()
+    .map(|(name, _, _, _)| *name)
+    .find(|name| lib_name.contains(name))
+}
+
+/// `path` parsed, through the [`cache`] of parsed libraries
+pub fn read_lib(path: impl AsRef<Path>) -> anyhow::Result<Library<DefaultCtx>> {
+  cache::read_lib(path)
+}
+
 pub const RUN: [(&str, usize, &str); 1] = [("10k_QMC", 10000, "QmcSample")];
 // const RUN: [(&str, usize, &str); 1] = [("100kMC", 100000, "McSample")];
 // [("golden", 50001, "QmcSample"), ("baseline", 10001, "McSample")];
@@ -304,16 +354,12 @@ fn pruned_lib() -> anyhow::Result<()> {
   .map(String::from)
   .collect();
   let file_name = "/data/junzhuo/tech/tsmc/22nm/tcbn22ullbwp30p140_110b/AN61001_20201222/TSMCHOME/digital/Front_End/timing_power_noise/NLDM/tcbn22ullbwp30p140_110b/tcbn22ullbwp30p140tt0p8v25c.lib";
-  if let Ok(mut library) = liberty_db::library::Library::<DefaultCtx>::parse_lib(
-    &std::fs::read_to_string(Path::new(file_name))?,
-  ) {
-    library.cell.retain(|f| cell_list.contains(&f.name));
-    let lib_path = "pruned.lib";
-    let mut writer = BufWriter::new(File::create(lib_path)?);
-    write!(&mut writer, "{}", library)?;
-    let data: BinaryHeap<_> = library.cell.iter().collect();
-    // let data: BinaryHeap<_> = a_lot_of_numbers.collect()
-  }
+  let library = extract::read_cells(file_name, &cell_list)?;
+  let lib_path = "pruned.lib";
+  let mut writer = BufWriter::new(File::create(lib_path)?);
+  write!(&mut writer, "{}", library)?;
+  let data: BinaryHeap<_> = library.cell.iter().collect();
+  // let data: BinaryHeap<_> = a_lot_of_numbers.collect()
   Ok(())
 }
 
@@ -336,14 +382,10 @@ fn pruned_lvf_lib() -> anyhow::Result<()> {
   .map(String::from)
   .collect();
   let file_name = "/data/junzhuo/tech/tsmc/22nm/tcbn22ullbwp30p140_110b/AN61001_20201222/TSMCHOME/digital/Front_End/LVF/CCS/tcbn22ullbwp30p140_110b/tcbn22ullbwp30p140tt0p8v25c_hm_lvf_p_ccs.lib";
-  if let Ok(mut library) = liberty_db::library::Library::<DefaultCtx>::parse_lib(
-    &std::fs::read_to_string(Path::new(file_name))?,
-  ) {
-    library.cell.retain(|f| cell_list.contains(&f.name));
-    let lib_path = "pruned_lvf.lib";
-    let mut writer = BufWriter::new(File::create(lib_path)?);
-    write!(&mut writer, "{}", library)?;
-  }
+  let library = extract::read_cells(file_name, &cell_list)?;
+  let lib_path = "pruned_lvf.lib";
+  let mut writer = BufWriter::new(File::create(lib_path)?);
+  write!(&mut writer, "{}", library)?;
   Ok(())
 }
 
@@ -378,13 +420,10 @@ fn valid_cells() -> anyhow::Result<()> {
   .into_iter()
   .collect();
   let file_name = "/data/junzhuo/tech/tsmc/22nm/tcbn22ullbwp30p140_110b/AN61001_20201222/TSMCHOME/digital/Front_End/timing_power_noise/NLDM/tcbn22ullbwp30p140_110b/tcbn22ullbwp30p140tt0p8v25c.lib";
-  if let Ok(library) = liberty_db::library::Library::<DefaultCtx>::parse_lib(
-    &std::fs::read_to_string(Path::new(file_name))?,
-  ) {
-    for cell in library.cell.iter() {
-      if !cell_list.contains(cell.name.as_str()) {
-        println!("{},", cell.name);
-      }
+  let library = read_lib(file_name)?;
+  for cell in library.cell.iter() {
+    if !cell_list.contains(cell.name.as_str()) {
+      println!("{},", cell.name);
     }
   }
   Ok(())
@@ -392,44 +431,17 @@ fn valid_cells() -> anyhow::Result<()> {
 
 #[test]
 fn replace_timing_baseline() -> anyhow::Result<()> {
-  let template_file = "pruned_active_lvf.lib";
-  let data1_file = "/code/char0425/baseline1/out/btdcell.lib";
-  let data2_file = "/code/char0425/baseline2/out/btdcell.lib";
-  match (
-    liberty_db::library::Library::<DefaultCtx>::parse_lib(&std::fs::read_to_string(
-      Path::new(template_file),
-    )?),
-    liberty_db::library::Library::<DefaultCtx>::parse_lib(&std::fs::read_to_string(
-      Path::new(data1_file),
-    )?),
-    liberty_db::library::Library::<DefaultCtx>::parse_lib(&std::fs::read_to_string(
-      Path::new(data2_file),
-    )?),
-  ) {
-    (Ok(mut template_lib), Ok(mut data1_lib), Ok(data2_lib)) => {
-      data1_lib
-        .cell
-        .insert(template_lib.cell.get("DFCNQD1BWP30P140").expect("msg").clone());
-      data1_lib.cell.extend(data2_lib.cell.into_iter());
-      for template_cell in template_lib.cell.iter_mut() {
-        let data_cell = data1_lib
-          .cell
-          .get_mut(&template_cell.name)
-          .expect(&format!("{:?}", template_cell.name));
-        for template_pin in template_cell.pin.iter_mut() {
-          let data_pin = data_cell
-            .pin
-            .get_mut(template_pin.name.as_ref())
-            .expect(&format!("{:?}", template_pin.name.as_ref()));
-          template_pin.timing = data_pin.timing.clone();
-        }
-      }
-      let lib_path = "pruned_baseline.lib";
-      let mut writer = BufWriter::new(File::create(lib_path)?);
-      write!(&mut writer, "{}", template_lib)?;
-    }
-    _ => todo!(),
-  }
+  let report = merge::merge_files(
+    "pruned_active_lvf.lib",
+    &[
+      "/code/char0425/baseline1/out/btdcell.lib",
+      "/code/char0425/baseline2/out/btdcell.lib",
+    ],
+    merge::Fields::Timing,
+    vec![merge::Skip::Cell("DFCNQD1BWP30P140".into())],
+    "pruned_baseline.lib",
+  )?;
+  print!("{report}");
   Ok(())
 }
 
@@ -494,44 +506,17 @@ fn lvf_lib() -> anyhow::Result<()> {
 
 #[test]
 fn collect() -> anyhow::Result<()> {
-  let template_file = "lvf.lib";
-  let data1_file = "/code/char0425/baseline1/out/btdcell.lib";
-  let data2_file = "/code/char0425/baseline2/out/btdcell.lib";
-  match (
-    liberty_db::library::Library::<DefaultCtx>::parse_lib(&std::fs::read_to_string(
-      Path::new(template_file),
-    )?),
-    liberty_db::library::Library::<DefaultCtx>::parse_lib(&std::fs::read_to_string(
-      Path::new(data1_file),
-    )?),
-    liberty_db::library::Library::<DefaultCtx>::parse_lib(&std::fs::read_to_string(
-      Path::new(data2_file),
-    )?),
-  ) {
-    (Ok(mut template_lib), Ok(mut data1_lib), Ok(data2_lib)) => {
-      data1_lib
-        .cell
-        .insert(template_lib.cell.get("DFCNQD1BWP30P140").expect("msg").clone());
-      data1_lib.cell.extend(data2_lib.cell.into_iter());
-      for template_cell in template_lib.cell.iter_mut() {
-        let data_cell = data1_lib
-          .cell
-          .get_mut(&template_cell.name)
-          .expect(&format!("{:?}", template_cell.name));
-        for template_pin in template_cell.pin.iter_mut() {
-          let data_pin = data_cell
-            .pin
-            .get_mut(template_pin.name.as_ref())
-            .expect(&format!("{:?}", template_pin.name.as_ref()));
-          template_pin.timing = data_pin.timing.clone();
-        }
-      }
-      let lib_path = "pruned_baseline.lib";
-      let mut writer = BufWriter::new(File::create(lib_path)?);
-      write!(&mut writer, "{}", template_lib)?;
-    }
-    _ => unreachable!(),
-  }
+  let report = merge::merge_files(
+    "lvf.lib",
+    &[
+      "/code/char0425/baseline1/out/btdcell.lib",
+      "/code/char0425/baseline2/out/btdcell.lib",
+    ],
+    merge::Fields::Timing,
+    vec![merge::Skip::Cell("DFCNQD1BWP30P140".into())],
+    "pruned_baseline.lib",
+  )?;
+  print!("{report}");
   Ok(())
 }
 
@@ -547,74 +532,68 @@ fn main() -> anyhow::Result<()> {
   let run_dir = fs::canonicalize(&Path::new("../run"))?;
   let cpu_num: usize = 32;
   let mut task_list = Vec::new();
-  if let Ok(mut library) = liberty_db::library::Library::<DefaultCtx>::parse_lib(
-    &std::fs::read_to_string(Path::new(file_name))?,
-  ) {
-    let mut _library = library.clone();
-    _library.cell.clear();
-    for (cell_group, (pin_name, related, when_str, rise), cell_names) in CELL_GROUP {
-      let mut cells = GroupSet::<Cell<DefaultCtx>>::default();
-      for &cell_name in cell_names.iter() {
-        let mut cell = library.cell.take(cell_name).expect("msg");
-        let when = if *when_str == "" {
-          None
-        } else {
-          Some(cell.parse_logic_boolexpr(when_str)?)
-        };
-        cell.leakage_power.clear();
-        for pin in cell.pin.iter_mut() {
-          pin.internal_power.clear();
-          if pin.name.as_ref() == (*pin_name).into() {
-            pin
-              .timing
-              .retain(|t| t.related_pin.contains(related) && t.when == when);
-            for timing in pin.timing.iter_mut() {
-              if *rise {
-                timing.cell_fall = None;
-                timing.fall_transition = None;
-                timing.rise_constraint = None;
-                timing.fall_constraint = None;
-              } else {
-                timing.cell_rise = None;
-                timing.rise_transition = None;
-                timing.rise_constraint = None;
-                timing.fall_constraint = None;
-              }
+  let mut library = read_lib(file_name)?;
+  let mut _library = library.clone();
+  _library.cell.clear();
+  for (cell_group, (pin_name, related, when_str, rise), cell_names) in CELL_GROUP {
+    let mut cells = GroupSet::<Cell<DefaultCtx>>::default();
+    for &cell_name in cell_names.iter() {
+      let mut cell = library.cell.take(cell_name).expect("msg");
+      let when =
+        if *when_str == "" { None } else { Some(cell.parse_logic_boolexpr(when_str)?) };
+      cell.leakage_power.clear();
+      for pin in cell.pin.iter_mut() {
+        pin.internal_power.clear();
+        if pin.name.as_ref() == (*pin_name).into() {
+          pin
+            .timing
+            .retain(|t| t.related_pin.contains(related) && t.when == when);
+          for timing in pin.timing.iter_mut() {
+            if *rise {
+              timing.cell_fall = None;
+              timing.fall_transition = None;
+              timing.rise_constraint = None;
+              timing.fall_constraint = None;
+            } else {
+              timing.cell_rise = None;
+              timing.rise_transition = None;
+              timing.rise_constraint = None;
+              timing.fall_constraint = None;
             }
-          } else {
-            pin.timing.clear();
           }
+        } else {
+          pin.timing.clear();
         }
-        cells.insert(cell);
       }
-      _library.cell = cells;
-      let lib_path = temp_dir.join(format!("{cell_group}.lib"));
-      let mut writer = BufWriter::new(File::create(lib_path.clone())?);
-      write!(&mut writer, "{}", _library)?;
-      for (run_name, sample_num, sample_type) in RUN {
-        for (pvt_name, p, v, t) in PVT {
-          let name = format!("{cell_group}_{run_name}_{pvt_name}");
-          let yaml_path = conf_dir.join(format!("{name}.yaml"));
-          let _cpu_num = cell_names.len();
-          serde_yaml::to_writer(
-            BufWriter::new(File::create(yaml_path.clone())?),
-            &Config {
-              Name: name,
-              Voltage: *v,
-              Temperature: *t,
-              LibFilePath: format!("{}", lib_path.display()),
-              NetListPath: netlist_path.to_string(),
-              ModelPath: model_path.to_string(),
-              ModelSection: p.to_string(),
-              LvfType: sample_type.to_string(),
-              LVFSamplingNum: sample_num,
-              NumCPU: cell_names.len(),
-              HspicePath: hspice_path.to_string(),
-              CellNameList: cell_names.iter().map(ToString::to_string).collect(),
-            },
-          )?;
-          task_list.push((_cpu_num, format!("{btdcell_path} {}&", yaml_path.display())));
-        }
+      cells.insert(cell);
+    }
+    _library.cell = cells;
+    let lib_path = temp_dir.join(format!("{cell_group}.lib"));
+    let mut writer = BufWriter::new(File::create(lib_path.clone())?);
+    write!(&mut writer, "{}", _library)?;
+    for (run_name, sample_num, sample_type) in RUN {
+      for (pvt_name, p, v, t) in PVT {
+        let name = format!("{cell_group}_{run_name}_{pvt_name}");
+        let yaml_path = conf_dir.join(format!("{name}.yaml"));
+        let _cpu_num = cell_names.len();
+        serde_yaml::to_writer(
+          BufWriter::new(File::create(yaml_path.clone())?),
+          &Config {
+            Name: name,
+            Voltage: *v,
+            Temperature: *t,
+            LibFilePath: format!("{}", lib_path.display()),
+            NetListPath: netlist_path.to_string(),
+            ModelPath: model_path.to_string(),
+            ModelSection: p.to_string(),
+            LvfType: sample_type.to_string(),
+            LVFSamplingNum: sample_num,
+            NumCPU: cell_names.len(),
+            HspicePath: hspice_path.to_string(),
+            CellNameList: cell_names.iter().map(ToString::to_string).collect(),
+          },
+        )?;
+        task_list.push((_cpu_num, format!("{btdcell_path} {}&", yaml_path.display())));
       }
     }
   }
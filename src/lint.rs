@@ -0,0 +1,460 @@
+//! Liberty checks to run before handing a library to Library Compiler.
+//!
+//! [`lint`] works on the parsed library. Duplicate timing groups are merged by the
+//! parser, so [`duplicate_timings`] looks for them in the text instead.
+use liberty_db::{
+  cell::Cell,
+  timing::{Timing, TimingTableLookUp},
+  DefaultCtx, Library,
+};
+use serde::Serialize;
+use std::{collections::HashMap, fmt, path::Path};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rule {
+  NonIncreasingIndex,
+  TemplateSize,
+  MissingTemplate,
+  DuplicateTiming,
+  MissingRelatedPin,
+  BadValue,
+  WhenSdfCond,
+  MissingLvfTable,
+  LvfShape,
+  MissingLibraryAttribute,
+}
+
+impl fmt::Display for Rule {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(match self {
+      Rule::NonIncreasingIndex => "non-increasing-index",
+      Rule::TemplateSize => "template-size",
+      Rule::MissingTemplate => "missing-template",
+      Rule::DuplicateTiming => "duplicate-timing",
+      Rule::MissingRelatedPin => "missing-related-pin",
+      Rule::BadValue => "bad-value",
+      Rule::WhenSdfCond => "when-sdf-cond",
+      Rule::MissingLvfTable => "missing-lvf-table",
+      Rule::LvfShape => "lvf-shape",
+      Rule::MissingLibraryAttribute => "missing-library-attribute",
+    })
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Finding {
+  pub rule: Rule,
+  pub path: String,
+  pub message: String,
+}
+
+impl fmt::Display for Finding {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "[{}] {}: {}", self.rule, self.path, self.message)
+  }
+}
+
+/// The timing tables of `timing` by attribute name
+pub(crate) fn timing_tables(
+  timing: &Timing<DefaultCtx>,
+) -> [(&'static str, &Option<TimingTableLookUp<DefaultCtx>>); 10] {
+  [
+    ("cell_rise", &timing.cell_rise),
+    ("cell_fall", &timing.cell_fall),
+    ("rise_transition", &timing.rise_transition),
+    ("fall_transition", &timing.fall_transition),
+    ("rise_constraint", &timing.rise_constraint),
+    ("fall_constraint", &timing.fall_constraint),
+    ("retaining_rise", &timing.retaining_rise),
+    ("retaining_fall", &timing.retaining_fall),
+    ("retain_rise_slew", &timing.retain_rise_slew),
+    ("retain_fall_slew", &timing.retain_fall_slew),
+  ]
+}
+
+/// `cell/pin/timing(related sense type when ...)` of a timing group
+pub(crate) fn timing_path(cell: &str, pin: &str, timing: &Timing<DefaultCtx>) -> String {
+  let mut path = format!("{cell}/{pin}/timing({}", timing.related_pin);
+  if let Some(sense) = &timing.timing_sense {
+    path.push_str(&format!(" {sense}"));
+  }
+  if let Some(timing_type) = &timing.timing_type {
+    path.push_str(&format!(" {timing_type}"));
+  }
+  if let Some(when) = &timing.when {
+    path.push_str(&format!(" when {when}"));
+  }
+  path.push(')');
+  path
+}
+
+fn is_increasing(index: &[f64]) -> bool {
+  index.windows(2).all(|w| w[0] < w[1])
+}
+
+/// `sdf_cond` in Liberty boolean syntax: `A == 1'b1 && B == 1'b0` becomes `A & !B`
+pub fn sdf_to_boolean(sdf: &str) -> String {
+  let mut spaced = sdf.replace("&&", " & ").replace("||", " | ");
+  for op in ["==", "!=", "(", ")", "~"] {
+    spaced = spaced.replace(op, &format!(" {op} "));
+  }
+  // `!` alone, not the one of `!=`
+  let spaced = spaced.replace("! ", " ! ").replace("!=", " != ");
+  let tokens: Vec<&str> = spaced.split_whitespace().collect();
+  let mut out = Vec::new();
+  let mut i = 0;
+  while i < tokens.len() {
+    let token = tokens[i];
+    let value = |t: &str| match t {
+      "1'b1" | "1'B1" | "1" => Some(true),
+      "1'b0" | "1'B0" | "0" => Some(false),
+      _ => None,
+    };
+    match (tokens.get(i + 1), tokens.get(i + 2).and_then(|t| value(t))) {
+      (Some(&op), Some(v)) if op == "==" || op == "!=" => {
+        let high = (op == "==") == v;
+        out.push(if high { token.to_string() } else { format!("!{token}") });
+        i += 3;
+      }
+      _ => {
+        out.push(if token == "~" { "!".into() } else { token.to_string() });
+        i += 1;
+      }
+    }
+  }
+  out.join(" ").replace("! ", "!")
+}
+
+fn lint_table(
+  lib: &Library<DefaultCtx>,
+  path: &str,
+  name: &str,
+  table: &TimingTableLookUp<DefaultCtx>,
+  out: &mut Vec<Finding>,
+) {
+  let mut push = |rule, message: String| {
+    out.push(Finding { rule, path: format!("{path}/{name}"), message })
+  };
+  for (index_name, index) in [("index_1", &table.index_1), ("index_2", &table.index_2)] {
+    if !is_increasing(index) {
+      push(Rule::NonIncreasingIndex, format!("{index_name} {index:?}"));
+    }
+  }
+  if !table.name.is_empty() && table.name != "scalar" {
+    match lib.lu_table_template.get(&table.name) {
+      None => push(Rule::MissingTemplate, format!("lu_table_template {}", table.name)),
+      Some(template) => {
+        let len = |own: &[f64], template: &Option<Vec<f64>>| {
+          if own.is_empty() {
+            template.as_ref().map_or(1, |t| t.len().max(1))
+          } else {
+            own.len()
+          }
+        };
+        let expected =
+          len(&table.index_1, &template.index_1) * len(&table.index_2, &template.index_2);
+        if table.values.len() != expected {
+          push(
+            Rule::TemplateSize,
+            format!(
+              "{} values, template {} expects {expected}",
+              table.values.len(),
+              table.name
+            ),
+          );
+        }
+      }
+    }
+  }
+  let bad = |v: &f64| v.is_nan() || *v < 0.0;
+  if matches!(name, "cell_rise" | "cell_fall" | "rise_transition" | "fall_transition") {
+    let points: Vec<usize> =
+      (0..table.values.len()).filter(|i| bad(&table.values[*i])).collect();
+    if !points.is_empty() {
+      push(Rule::BadValue, format!("NaN or negative values at {points:?}"));
+    }
+  }
+  let points: Vec<usize> = (0..table.lvf_values.len())
+    .filter(|i| bad(&table.lvf_values[*i].std_dev))
+    .collect();
+  if !points.is_empty() {
+    push(Rule::BadValue, format!("NaN or negative ocv_std_dev at {points:?}"));
+  }
+}
+
+fn lint_timing(
+  lib: &Library<DefaultCtx>,
+  cell: &Cell<DefaultCtx>,
+  pin: &str,
+  timing: &Timing<DefaultCtx>,
+  out: &mut Vec<Finding>,
+) {
+  let path = timing_path(&cell.name, pin, timing);
+  let timing_type = timing.timing_type.as_ref().map(ToString::to_string);
+  let needs_related_pin =
+    !matches!(timing_type.as_deref(), Some("min_pulse_width" | "minimum_period"));
+  if needs_related_pin && timing.related_pin.to_string().is_empty() {
+    let message = "timing group without related_pin".into();
+    out.push(Finding {
+      rule: Rule::MissingRelatedPin,
+      path: path.clone(),
+      message,
+    });
+  }
+  if let Some(sdf_cond) = &timing.sdf_cond {
+    let message = match (
+      &timing.when,
+      cell.parse_logic_boolexpr(&sdf_to_boolean(&sdf_cond.to_string())),
+    ) {
+      (_, Err(e)) => Some(format!("sdf_cond \"{sdf_cond}\" does not parse: {e:?}")),
+      (None, Ok(_)) => Some(format!("sdf_cond \"{sdf_cond}\" without when")),
+      (Some(when), Ok(cond)) => {
+        (*when != cond).then(|| format!("when \"{when}\" but sdf_cond \"{sdf_cond}\""))
+      }
+    };
+    if let Some(message) = message {
+      out.push(Finding {
+        rule: Rule::WhenSdfCond,
+        path: path.clone(),
+        message,
+      });
+    }
+  }
+  for (name, table) in timing_tables(timing) {
+    if let Some(table) = table {
+      lint_table(lib, &path, name, table, out);
+    }
+  }
+  let ocv_sigma = [
+    ("ocv_sigma_cell_rise", &timing.ocv_sigma_cell_rise),
+    ("ocv_sigma_cell_fall", &timing.ocv_sigma_cell_fall),
+    ("ocv_sigma_rise_transition", &timing.ocv_sigma_rise_transition),
+    ("ocv_sigma_fall_transition", &timing.ocv_sigma_fall_transition),
+  ];
+  for (name, tables) in ocv_sigma {
+    for table in tables.iter() {
+      let values = &table.values.inner;
+      let points: Vec<usize> = (0..values.len())
+        .filter(|i| values[*i].is_nan() || values[*i] < 0.0)
+        .collect();
+      if !points.is_empty() {
+        out.push(Finding {
+          rule: Rule::BadValue,
+          path: format!("{path}/{name}"),
+          message: format!("NaN or negative sigma at {points:?}"),
+        });
+      }
+    }
+  }
+}
+
+/// Findings on the parsed library
+pub fn lint(lib: &Library<DefaultCtx>) -> Vec<Finding> {
+  let mut out = Vec::new();
+  for cell in lib.cell.iter() {
+    for pin in cell.pin.iter() {
+      let pin_name = pin.name.to_string();
+      for timing in pin.timing.iter() {
+        lint_timing(lib, cell, &pin_name, timing, &mut out);
+      }
+    }
+  }
+  out
+}
+
+pub(crate) enum Event<'a> {
+  Open(&'a str),
+  Statement(&'a str),
+  Close(&'a str),
+}
+
+/// Calls `f` with the headers of the enclosing groups, library first, for every
+/// group opened or closed and every statement of the Liberty `text`
+pub(crate) fn walk(text: &str, mut f: impl FnMut(&[String], Event<'_>)) {
+  let mut stack: Vec<String> = Vec::new();
+  let mut statement = String::new();
+  let (mut in_string, mut in_comment) = (false, false);
+  let mut chars = text.chars().peekable();
+  while let Some(c) = chars.next() {
+    if in_comment {
+      if c == '*' && chars.peek() == Some(&'/') {
+        chars.next();
+        in_comment = false;
+      }
+      continue;
+    }
+    if c == '/' && chars.peek() == Some(&'*') {
+      chars.next();
+      in_comment = true;
+      continue;
+    }
+    if c == '"' {
+      in_string = !in_string;
+    }
+    if in_string || !matches!(c, '{' | '}' | ';') {
+      statement.push(c);
+      continue;
+    }
+    let text = std::mem::take(&mut statement);
+    let text = text.trim();
+    match c {
+      '{' => {
+        f(&stack, Event::Open(text));
+        stack.push(text.to_string());
+      }
+      ';' => f(&stack, Event::Statement(text)),
+      _ => {
+        if let Some(header) = stack.pop() {
+          f(&stack, Event::Close(&header));
+        }
+      }
+    }
+  }
+}
+
+/// `(name, value)` of a `name : value` statement, unquoted
+pub(crate) fn simple_attribute(statement: &str) -> Option<(&str, &str)> {
+  let (name, value) = statement.split_once(':')?;
+  Some((name.trim(), value.trim().trim_matches('"')))
+}
+
+pub(crate) fn group_name(header: &str) -> &str {
+  header.split('(').next().unwrap_or(header).trim()
+}
+
+/// Timing groups that repeat `related_pin`, `when`, `timing_type` and
+/// `timing_sense` within one pin of the Liberty `text`
+pub fn duplicate_timings(text: &str) -> Vec<Finding> {
+  const KEYS: [&str; 4] = ["related_pin", "when", "timing_type", "timing_sense"];
+  let mut timing_keys: Vec<[String; 4]> = Vec::new();
+  let mut seen: HashMap<(String, [String; 4]), usize> = HashMap::new();
+  let mut out = Vec::new();
+  walk(text, |stack, event| match event {
+    Event::Open(header) if group_name(header) == "timing" => {
+      timing_keys.push(Default::default())
+    }
+    Event::Statement(statement)
+      if stack.last().is_some_and(|g| group_name(g) == "timing") =>
+    {
+      if let Some((key, value)) = simple_attribute(statement) {
+        if let Some(k) = KEYS.iter().position(|k| *k == key) {
+          let value = value.split_whitespace().collect::<Vec<_>>().join(" ");
+          timing_keys.last_mut().expect("inside timing")[k] = value;
+        }
+      }
+    }
+    Event::Close(header) if group_name(header) == "timing" => {
+      let keys = timing_keys.pop().expect("inside timing");
+      let parent = stack.iter().skip(1).cloned().collect::<Vec<_>>().join("/");
+      let count = seen.entry((parent.clone(), keys.clone())).or_default();
+      *count += 1;
+      if *count == 2 {
+        out.push(Finding {
+          rule: Rule::DuplicateTiming,
+          path: parent,
+          message: format!(
+            "timing group repeated: related_pin {} when {} timing_type {} \
+             timing_sense {}",
+            keys[0], keys[1], keys[2], keys[3]
+          ),
+        });
+      }
+    }
+    _ => {}
+  });
+  out
+}
+
+/// [`duplicate_timings`] and [`lint`] of the Liberty `text`
+pub fn lint_text(text: &str) -> anyhow::Result<Vec<Finding>> {
+  let lib =
+    Library::<DefaultCtx>::parse_lib(text).map_err(|e| anyhow::anyhow!("{e:?}"))?;
+  let mut findings = duplicate_timings(text);
+  findings.extend(lint(&lib));
+  Ok(findings)
+}
+
+/// [`lint_text`] of the library at `path`
+pub fn lint_file(path: impl AsRef<Path>) -> anyhow::Result<Vec<Finding>> {
+  let path = path.as_ref();
+  let text = std::fs::read_to_string(path)
+    .map_err(|e| anyhow::anyhow!("read {}: {e}", path.display()))?;
+  lint_text(&text).map_err(|e| anyhow::anyhow!("parse {}: {e}", path.display()))
+}
+
+#[test]
+fn lint_rules() -> anyhow::Result<()> {
+  assert_eq!(sdf_to_boolean("A1 == 1'b1 && A2 == 1'b0"), "A1 & !A2");
+  assert_eq!(sdf_to_boolean("(B != 1'b1) || ~A"), "( !B ) | !A");
+  let text = r#"library (small) {
+  time_unit : "1ns";
+  lu_table_template (t2) {
+    variable_1 : input_net_transition;
+    variable_2 : total_output_net_capacitance;
+    index_1 ("1, 2");
+    index_2 ("1, 2");
+  }
+  cell (ND2) {
+    pin (A1) { direction : input; }
+    pin (A2) { direction : input; }
+    pin (ZN) {
+      direction : output;
+      function : "!(A1 & A2)";
+      timing () {
+        related_pin : "A1";
+        when : "A2";
+        timing_sense : negative_unate;
+        timing_type : combinational;
+        sdf_cond : "A2 == 1'b0";
+        cell_rise (t3) {
+          index_1 ("2, 1");
+          index_2 ("1, 2");
+          values ("1, 2", "-3, 4");
+        }
+      }
+      timing () {
+        related_pin : "A1";
+        when : "A2";
+        timing_sense : negative_unate;
+        timing_type : combinational;
+        cell_fall (t2) {
+          index_1 ("1, 2");
+          index_2 ("1, 2");
+          values ("1, 2", "3, 4");
+        }
+      }
+      timing () {
+        related_pin : "A2";
+        rise_transition (t2) {
+          index_1 ("1, 2, 3");
+          values ("1, 2", "3, 4");
+        }
+      }
+    }
+  }
+}
+"#;
+  let duplicates = duplicate_timings(text);
+  assert_eq!(duplicates.len(), 1);
+  assert_eq!(duplicates[0].path, "cell (ND2)/pin (ZN)");
+  assert_eq!(
+    duplicates[0].message,
+    "timing group repeated: related_pin A1 when A2 timing_type combinational \
+     timing_sense negative_unate"
+  );
+  let lib =
+    Library::<DefaultCtx>::parse_lib(text).map_err(|e| anyhow::anyhow!("{e:?}"))?;
+  let rules: Vec<Rule> = lint(&lib).iter().map(|f| f.rule).collect();
+  for rule in [
+    Rule::NonIncreasingIndex,
+    Rule::TemplateSize,
+    Rule::MissingTemplate,
+    Rule::BadValue,
+    Rule::WhenSdfCond,
+  ] {
+    assert!(rules.contains(&rule), "{rule} in {rules:?}");
+  }
+  assert!(!rules.contains(&Rule::MissingRelatedPin));
+  Ok(())
+}
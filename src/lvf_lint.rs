@@ -0,0 +1,280 @@
+//! Completeness of the LVF tables of a library, checked before release.
+//!
+//! Works on the text: the parser drops a nominal table together with its LVF
+//! tables when one of `ocv_mean_shift_*`, `ocv_std_dev_*` and `ocv_skewness_*`
+//! is missing, so the parsed library cannot tell a partial arc from a plain one.
+use crate::lint::{group_name, simple_attribute, walk, Event, Finding, Rule};
+use std::collections::{BTreeMap, BTreeSet};
+
+pub const NOMINAL_TABLES: [&str; 4] =
+  ["cell_rise", "cell_fall", "rise_transition", "fall_transition"];
+pub const MOMENT_PREFIXES: [&str; 3] =
+  ["ocv_mean_shift_", "ocv_std_dev_", "ocv_skewness_"];
+/// Library-level LVF groups the STA tool needs to read the OCV tables: the
+/// templates of the `ocv_sigma_*` tables
+pub const LIBRARY_ATTRIBUTES: [&str; 1] = ["ocv_table_template"];
+
+#[derive(Debug, Clone, PartialEq, Default)]
+struct Table {
+  index_1: Vec<f64>,
+  index_2: Vec<f64>,
+  values: usize,
+}
+
+#[derive(Debug, Default)]
+struct TimingGroup {
+  related_pin: String,
+  timing_sense: String,
+  timing_type: String,
+  when: String,
+  tables: BTreeMap<String, Table>,
+}
+
+/// `NAME` of a `group (NAME)` header
+fn group_arg(header: &str) -> &str {
+  header
+    .split_once('(')
+    .map_or("", |(_, rest)| rest.trim_end().trim_end_matches(')'))
+    .trim()
+    .trim_matches('"')
+}
+
+/// Numbers of a complex attribute like `values ("1, 2", \ "3, 4")`
+fn numbers(statement: &str) -> Vec<f64> {
+  let Some((_, args)) = statement.split_once('(') else {
+    return Vec::new();
+  };
+  args
+    .split(|c: char| c == ',' || c == '"' || c == '\\' || c == ')' || c.is_whitespace())
+    .filter_map(|s| s.parse().ok())
+    .collect()
+}
+
+fn check_timing(path: &str, timing: &TimingGroup, out: &mut Vec<Finding>) {
+  for nominal_name in NOMINAL_TABLES {
+    let Some(nominal) = timing.tables.get(nominal_name) else {
+      continue;
+    };
+    let table_path = format!("{path}/{nominal_name}");
+    let mut missing = Vec::new();
+    for prefix in MOMENT_PREFIXES {
+      let name = format!("{prefix}{nominal_name}");
+      let Some(table) = timing.tables.get(&name) else {
+        missing.push(name);
+        continue;
+      };
+      let mut differs = Vec::new();
+      if table.index_1 != nominal.index_1 {
+        differs.push(format!("index_1 {:?} vs {:?}", table.index_1, nominal.index_1));
+      }
+      if table.index_2 != nominal.index_2 {
+        differs.push(format!("index_2 {:?} vs {:?}", table.index_2, nominal.index_2));
+      }
+      if table.values != nominal.values {
+        differs.push(format!("{} values vs {}", table.values, nominal.values));
+      }
+      if !differs.is_empty() {
+        out.push(Finding {
+          rule: Rule::LvfShape,
+          path: table_path.clone(),
+          message: format!("{name}: {}", differs.join(", ")),
+        });
+      }
+    }
+    if !missing.is_empty() {
+      out.push(Finding {
+        rule: Rule::MissingLvfTable,
+        path: table_path,
+        message: format!("missing {}", missing.join(", ")),
+      });
+    }
+  }
+}
+
+/// LVF findings on the Liberty `text`, with `required` library attributes
+pub fn lvf_completeness(text: &str, required: &[&str]) -> Vec<Finding> {
+  let mut out = Vec::new();
+  let mut library_names = BTreeSet::new();
+  let mut timing: Option<TimingGroup> = None;
+  let mut table: Option<(String, Table)> = None;
+  let path = |stack: &[String], timing: &TimingGroup| {
+    let mut path: Vec<&str> = stack.iter().skip(1).map(|h| group_arg(h)).collect();
+    path.pop();
+    // as lint::timing_path, so setup and hold arcs on one pin stay apart
+    let mut arc = format!("timing({}", timing.related_pin);
+    for attribute in [&timing.timing_sense, &timing.timing_type] {
+      if !attribute.is_empty() {
+        arc.push_str(&format!(" {attribute}"));
+      }
+    }
+    if !timing.when.is_empty() {
+      arc.push_str(&format!(" when {}", timing.when));
+    }
+    arc.push(')');
+    path.push(&arc);
+    path.join("/")
+  };
+  walk(text, |stack, event| {
+    let depth = stack.len();
+    match event {
+      Event::Open(header) | Event::Statement(header) if depth == 1 => {
+        let name = header.split(['(', ':']).next().unwrap_or(header).trim();
+        library_names.insert(name.to_string());
+      }
+      Event::Open(header) if group_name(header) == "timing" => {
+        timing = Some(TimingGroup::default())
+      }
+      Event::Open(header)
+        if timing.is_some()
+          && stack.last().is_some_and(|g| group_name(g) == "timing") =>
+      {
+        table = Some((group_name(header).to_string(), Table::default()));
+      }
+      Event::Statement(statement) => {
+        if let Some((_, table)) = table.as_mut() {
+          match group_name(statement) {
+            "index_1" => table.index_1 = numbers(statement),
+            "index_2" => table.index_2 = numbers(statement),
+            "values" => table.values = numbers(statement).len(),
+            _ => {}
+          }
+        } else if let Some(timing) = timing.as_mut() {
+          match simple_attribute(statement) {
+            Some(("related_pin", value)) => timing.related_pin = value.to_string(),
+            Some(("timing_sense", value)) => timing.timing_sense = value.to_string(),
+            Some(("timing_type", value)) => timing.timing_type = value.to_string(),
+            Some(("when", value)) => timing.when = value.to_string(),
+            _ => {}
+          }
+        }
+      }
+      Event::Close(header) if group_name(header) == "timing" => {
+        if let Some(timing) = timing.take() {
+          let mut stack = stack.to_vec();
+          stack.push(header.to_string());
+          check_timing(&path(&stack, &timing), &timing, &mut out);
+        }
+      }
+      Event::Close(_) => {
+        if let (Some((name, done)), Some(timing)) = (table.take(), timing.as_mut()) {
+          timing.tables.insert(name, done);
+        }
+      }
+      _ => {}
+    }
+  });
+  for name in required.iter().filter(|n| !library_names.contains(**n)) {
+    out.push(Finding {
+      rule: Rule::MissingLibraryAttribute,
+      path: String::new(),
+      message: format!("library has no {name}"),
+    });
+  }
+  out
+}
+
+/// `cell/pin/timing(...)` of the arcs with an incomplete or mismatched LVF table
+pub fn incomplete_arcs(findings: &[Finding]) -> BTreeSet<String> {
+  findings
+    .iter()
+    .filter(|f| matches!(f.rule, Rule::MissingLvfTable | Rule::LvfShape))
+    .filter_map(|f| f.path.rsplit_once('/').map(|(arc, _)| arc.to_string()))
+    .collect()
+}
+
+#[test]
+fn lvf_complete() {
+  let text = r#"library (small) {
+  delay_model : table_lookup;
+  time_unit : "1ns";
+  cell (INV) {
+    pin (ZN) {
+      timing () {
+        related_pin : "I";
+        timing_sense : negative_unate;
+        timing_type : combinational;
+        cell_rise (t2) {
+          index_1 ("1, 2");
+          index_2 ("1, 2");
+          values ("1, 2", \
+            "3, 4");
+        }
+        ocv_mean_shift_cell_rise (t2) {
+          index_1 ("1, 2");
+          index_2 ("1, 2");
+          values ("0, 0", "0, 0");
+        }
+        ocv_std_dev_cell_rise (t2) {
+          index_1 ("1, 3");
+          index_2 ("1, 2");
+          values ("0.1, 0.1", "0.1, 0.1");
+        }
+        ocv_skewness_cell_rise (t2) {
+          index_1 ("1, 2");
+          index_2 ("1, 2");
+          values ("0, 0", "0, 0");
+        }
+        rise_transition (t2) {
+          index_1 ("1, 2");
+          index_2 ("1, 2");
+          values ("1, 2", "3, 4");
+        }
+        ocv_std_dev_rise_transition (t2) {
+          index_1 ("1, 2");
+          index_2 ("1, 2");
+          values ("0.1, 0.1", "0.1, 0.1");
+        }
+      }
+    }
+  }
+}
+"#;
+  assert_eq!(numbers(r#"values ("1, 2", \ "3e-1, 4")"#), [1.0, 2.0, 0.3, 4.0]);
+  let findings = lvf_completeness(text, &LIBRARY_ATTRIBUTES);
+  let rules: Vec<(Rule, &str)> =
+    findings.iter().map(|f| (f.rule, f.path.as_str())).collect();
+  assert_eq!(
+    rules,
+    [
+      (Rule::LvfShape, "INV/ZN/timing(I negative_unate combinational)/cell_rise"),
+      (
+        Rule::MissingLvfTable,
+        "INV/ZN/timing(I negative_unate combinational)/rise_transition"
+      ),
+      (Rule::MissingLibraryAttribute, ""),
+    ]
+  );
+  assert!(findings[1].message.contains("ocv_mean_shift_rise_transition"));
+  assert_eq!(findings[2].message, "library has no ocv_table_template");
+  assert_eq!(
+    incomplete_arcs(&findings).into_iter().collect::<Vec<_>>(),
+    ["INV/ZN/timing(I negative_unate combinational)"]
+  );
+}
+
+#[test]
+fn lvf_release() -> anyhow::Result<()> {
+  let mut problems = 0;
+  for entry in std::fs::read_dir(".")? {
+    let path = entry?.path();
+    let name = path
+      .file_name()
+      .map(|n| n.to_string_lossy().to_string())
+      .unwrap_or_default();
+    if !(name.starts_with("pruned_active_lvf") && name.ends_with(".lib")) {
+      continue;
+    }
+    let findings =
+      lvf_completeness(&std::fs::read_to_string(&path)?, &LIBRARY_ATTRIBUTES);
+    for finding in findings.iter().filter(|f| f.rule == Rule::MissingLibraryAttribute) {
+      println!("{name}: {finding}");
+      problems += 1;
+    }
+    for arc in incomplete_arcs(&findings) {
+      println!("{name}: incomplete {arc}");
+      problems += 1;
+    }
+  }
+  anyhow::ensure!(problems == 0, "{problems} LVF problems before release");
+  Ok(())
+}
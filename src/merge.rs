@@ -0,0 +1,724 @@
+//! Merging timing data of several characterization outputs into a template.
+//!
+//! Every arc of every source must exist in the template, unless a [`Skip`] entry
+//! covers it. Template arcs no source covers keep their data and are reported.
+//!
+//! Tables are converted to the time and capacitance units of the template, each
+//! index after its variable in the source's `lu_table_template`. The nominal and
+//! the LVF grid of each table are checked against the template table it lands on.
+use crate::{compare::lvf_index, fill::resample};
+use liberty_db::{
+  table::Variable,
+  timing::{LVFValue, Timing, TimingTableLookUp},
+  units::CapacitiveLoadUnit,
+  DefaultCtx, Library,
+};
+use std::{
+  collections::HashMap,
+  fmt,
+  fs::File,
+  io::{BufWriter, Write},
+  path::Path,
+  str::FromStr,
+};
+
+/// What is taken from the sources
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Fields {
+  /// whole timing groups of each pin, including groups the template lacks
+  Timing,
+  /// delay, transition and constraint tables with their LVF values
+  #[default]
+  All,
+  /// nominal values of the delay, transition and constraint tables, the template keeps
+  /// its mean shifts
+  Nominal,
+  /// mean shift, std dev and skewness, on the template's nominal values
+  Ocv,
+}
+
+impl FromStr for Fields {
+  type Err = anyhow::Error;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "timing" => Ok(Fields::Timing),
+      "all" => Ok(Fields::All),
+      "nominal" => Ok(Fields::Nominal),
+      "ocv" => Ok(Fields::Ocv),
+      _ => anyhow::bail!("unknown field selection {s:?}"),
+    }
+  }
+}
+
+/// What happens to an arc found in more than one source
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Conflict {
+  #[default]
+  Error,
+  /// the earliest source wins
+  First,
+  /// the latest source wins
+  Last,
+}
+
+impl FromStr for Conflict {
+  type Err = anyhow::Error;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "error" => Ok(Conflict::Error),
+      "first" => Ok(Conflict::First),
+      "last" => Ok(Conflict::Last),
+      _ => anyhow::bail!("unknown conflict policy {s:?}"),
+    }
+  }
+}
+
+/// What happens to a source table whose grid differs from the template's
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GridPolicy {
+  #[default]
+  Reject,
+  /// bilinear resampling onto the template grid
+  Resample,
+}
+
+impl FromStr for GridPolicy {
+  type Err = anyhow::Error;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "reject" => Ok(GridPolicy::Reject),
+      "resample" => Ok(GridPolicy::Resample),
+      _ => anyhow::bail!("unknown grid policy {s:?}"),
+    }
+  }
+}
+
+/// Parts left as they are in the template
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Skip {
+  Cell(String),
+  /// `(cell, pin, related_pin)`
+  Arc(String, String, String),
+}
+
+impl FromStr for Skip {
+  type Err = anyhow::Error;
+  /// `CELL` or `CELL/PIN/RELATED_PIN`
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.split('/').collect::<Vec<_>>().as_slice() {
+      [cell] => Ok(Skip::Cell(cell.to_string())),
+      [cell, pin, related_pin] => {
+        Ok(Skip::Arc(cell.to_string(), pin.to_string(), related_pin.to_string()))
+      }
+      _ => anyhow::bail!("skip entry {s:?} is neither CELL nor CELL/PIN/RELATED_PIN"),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MergeOptions {
+  pub fields: Fields,
+  pub conflict: Conflict,
+  pub grid: GridPolicy,
+  pub skip: Vec<Skip>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MergeReport {
+  /// merged arcs, pins for [`Fields::Timing`]
+  pub arcs: usize,
+  /// `source N: arc/table` of every table resampled onto the template grid
+  pub resampled: Vec<String>,
+  /// template arcs, pins for [`Fields::Timing`], that no source covers
+  pub kept: Vec<String>,
+}
+
+impl fmt::Display for MergeReport {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for table in self.resampled.iter() {
+      writeln!(f, "resampled {table}")?;
+    }
+    for arc in self.kept.iter() {
+      writeln!(f, "kept {arc}")?;
+    }
+    Ok(())
+  }
+}
+
+impl MergeOptions {
+  fn skips_cell(&self, cell: &str) -> bool {
+    self.skip.iter().any(|s| matches!(s, Skip::Cell(c) if c == cell))
+  }
+  fn skips_arc(&self, cell: &str, pin: &str, related_pin: &str) -> bool {
+    self.skips_cell(cell)
+      || self.skip.iter().any(
+        |s| matches!(s, Skip::Arc(c, p, r) if c == cell && p == pin && r == related_pin),
+      )
+  }
+}
+
+/// Cell, pin and timing ids of an arc, for messages and bookkeeping
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ArcKey(String);
+
+impl ArcKey {
+  fn of(cell: &str, pin: &str, timing: Option<&Timing<DefaultCtx>>) -> Self {
+    let mut key = format!("{cell}/{pin}");
+    if let Some(t) = timing {
+      key.push_str(&format!("/{}", t.related_pin));
+      if let Some(sense) = &t.timing_sense {
+        key.push_str(&format!(" {sense}"));
+      }
+      if let Some(timing_type) = &t.timing_type {
+        key.push_str(&format!(" {timing_type}"));
+      }
+      if let Some(when) = &t.when {
+        key.push_str(&format!(" when {when}"));
+      }
+    }
+    Self(key)
+  }
+}
+
+impl fmt::Display for ArcKey {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(&self.0)
+  }
+}
+
+/// Factors from source to template units
+#[derive(Debug, Clone, Copy)]
+struct Units {
+  time: f64,
+  capacitance: f64,
+}
+
+impl Units {
+  fn between(source: &Library<DefaultCtx>, template: &Library<DefaultCtx>) -> Self {
+    let capacitance = |lib: &Library<DefaultCtx>| {
+      lib
+        .capacitive_load_unit
+        .as_ref()
+        .map_or_else(|| CapacitiveLoadUnit::default().value(), CapacitiveLoadUnit::value)
+    };
+    Self {
+      time: source.time_unit.value() / template.time_unit.value(),
+      capacitance: capacitance(source) / capacitance(template),
+    }
+  }
+  /// Factors of `index_1`, `index_2` and the values of `table`, after the
+  /// variables of its `lu_table_template` in `lib`
+  fn of(
+    self,
+    lib: &Library<DefaultCtx>,
+    table: &TimingTableLookUp<DefaultCtx>,
+  ) -> anyhow::Result<[f64; 3]> {
+    let template = lib.lu_table_template.get(table.name.as_str());
+    let factor = |variable: Option<&Variable>, index: &[f64]| match variable {
+      Some(Variable::Time(_)) => Ok(self.time),
+      Some(Variable::Capacitance(_)) => Ok(self.capacitance),
+      Some(Variable::Scalar(_)) => Ok(1.0),
+      _ if index.is_empty() => Ok(1.0),
+      Some(variable) => anyhow::bail!("{}: cannot convert {variable:?}", table.name),
+      None => anyhow::bail!("{}: no lu_table_template variable", table.name),
+    };
+    Ok([
+      factor(template.and_then(|t| t.variable_1.as_ref()), &table.index_1)?,
+      factor(template.and_then(|t| t.variable_2.as_ref()), &table.index_2)?,
+      self.time,
+    ])
+  }
+}
+
+fn same_index(a: &[f64], b: &[f64]) -> bool {
+  a.len() == b.len()
+    && a
+      .iter()
+      .zip(b.iter())
+      .all(|(a, b)| (a - b).abs() <= 1e-9 * a.abs().max(b.abs()))
+}
+
+/// `source` in template units on the grid of `template`, and whether it was resampled
+fn fit(
+  source: &TimingTableLookUp<DefaultCtx>,
+  template: Option<&TimingTableLookUp<DefaultCtx>>,
+  [index_1, index_2, value]: [f64; 3],
+  grid: GridPolicy,
+) -> anyhow::Result<(TimingTableLookUp<DefaultCtx>, bool)> {
+  let mut table = source.clone();
+  for (index, factor) in [
+    (&mut table.index_1, index_1),
+    (&mut table.index_2, index_2),
+    (&mut table.lvf_index_1, index_1),
+    (&mut table.lvf_index_2, index_2),
+  ] {
+    index.iter_mut().for_each(|x| *x *= factor);
+  }
+  table.values.iter_mut().for_each(|x| *x *= value);
+  for lvf in table.lvf_values.iter_mut() {
+    lvf.mean *= value;
+    lvf.std_dev *= value;
+  }
+  let Some(template) = template else {
+    return Ok((table, false));
+  };
+  let lvf_1 = lvf_index(&table.lvf_index_1, &table.index_1);
+  let lvf_2 = lvf_index(&table.lvf_index_2, &table.index_2);
+  let to_1 = lvf_index(&template.lvf_index_1, &template.index_1);
+  let to_2 = lvf_index(&template.lvf_index_2, &template.index_2);
+  let lvf_points = lvf_1.len().max(1) * lvf_2.len().max(1);
+  if !table.lvf_values.is_empty() && table.lvf_values.len() != lvf_points {
+    anyhow::bail!(
+      "{}: {} LVF values on a {} x {} LVF grid",
+      table.name,
+      table.lvf_values.len(),
+      lvf_1.len(),
+      lvf_2.len()
+    );
+  }
+  if same_index(&table.index_1, &template.index_1)
+    && same_index(&table.index_2, &template.index_2)
+    && same_index(lvf_1, to_1)
+    && same_index(lvf_2, to_2)
+  {
+    return Ok((table, false));
+  }
+  if grid == GridPolicy::Reject {
+    anyhow::bail!(
+      "{}: grid {:?} x {:?}, LVF {lvf_1:?} x {lvf_2:?}, differs from the template's \
+       {:?} x {:?}, LVF {to_1:?} x {to_2:?}",
+      table.name,
+      table.index_1,
+      table.index_2,
+      template.index_1,
+      template.index_2
+    );
+  }
+  let values = resample(
+    &table.index_1,
+    &table.index_2,
+    &table.values,
+    &template.index_1,
+    &template.index_2,
+  );
+  let lvf_values = if table.lvf_values.is_empty() {
+    Vec::new()
+  } else {
+    let nominal = resample(&table.index_1, &table.index_2, &table.values, lvf_1, lvf_2);
+    let to_nominal = resample(&template.index_1, &template.index_2, &values, to_1, to_2);
+    let onto = |f: &dyn Fn(&LVFValue, f64) -> f64| {
+      let component: Vec<f64> = table
+        .lvf_values
+        .iter()
+        .zip(nominal.iter())
+        .map(|(v, n)| f(v, *n))
+        .collect();
+      resample(lvf_1, lvf_2, &component, to_1, to_2)
+    };
+    let shift = onto(&|v, n| v.mean - n);
+    let std_dev = onto(&|v, _| v.std_dev);
+    let skewness = onto(&|v, _| v.skewness);
+    (0..to_nominal.len())
+      .map(|i| LVFValue {
+        mean: to_nominal[i] + shift[i],
+        std_dev: std_dev[i],
+        skewness: skewness[i],
+      })
+      .collect()
+  };
+  table.index_1.clone_from(&template.index_1);
+  table.index_2.clone_from(&template.index_2);
+  table.lvf_index_1.clone_from(&template.lvf_index_1);
+  table.lvf_index_2.clone_from(&template.lvf_index_2);
+  table.size1 = template.size1;
+  table.size2 = template.size2;
+  table.values = values;
+  table.lvf_values = lvf_values;
+  Ok((table, true))
+}
+
+fn merge_table(
+  fields: Fields,
+  template: &mut Option<TimingTableLookUp<DefaultCtx>>,
+  source: &Option<TimingTableLookUp<DefaultCtx>>,
+) -> anyhow::Result<()> {
+  let Some(source) = source else {
+    return Ok(());
+  };
+  let template = match (fields, template.as_mut()) {
+    (Fields::Timing | Fields::All, _) | (Fields::Nominal, None) => {
+      *template = Some(source.clone());
+      return Ok(());
+    }
+    (Fields::Ocv, None) => {
+      anyhow::bail!("{}: no nominal table in the template", source.name)
+    }
+    (_, Some(template)) => template,
+  };
+  if template.values.len() != source.values.len() {
+    anyhow::bail!(
+      "{}: {} values in the template, {} in the source",
+      source.name,
+      template.values.len(),
+      source.values.len()
+    );
+  }
+  let shifts: Vec<f64> = template
+    .lvf_values
+    .iter()
+    .zip(template.values.iter())
+    .map(|(v, n)| v.mean - n)
+    .collect();
+  match fields {
+    Fields::Nominal => {
+      template.values.clone_from(&source.values);
+      for ((lvf, nominal), shift) in
+        template.lvf_values.iter_mut().zip(template.values.iter()).zip(shifts)
+      {
+        lvf.mean = nominal + shift;
+      }
+    }
+    Fields::Ocv => {
+      if source.lvf_values.len() != source.values.len() {
+        anyhow::bail!("{}: no LVF values in the source", source.name);
+      }
+      template.lvf_values = source
+        .lvf_values
+        .iter()
+        .zip(source.values.iter())
+        .zip(template.values.iter())
+        .map(|((lvf, source_nominal), nominal)| {
+          let mut lvf = *lvf;
+          lvf.mean += nominal - source_nominal;
+          lvf
+        })
+        .collect();
+    }
+    Fields::Timing | Fields::All => unreachable!(),
+  }
+  Ok(())
+}
+
+/// Merges `sources` into `template`, reports the merged arcs (pins for
+/// [`Fields::Timing`]), the resampled tables and the template arcs kept as they were.
+pub fn merge(
+  template: &mut Library<DefaultCtx>,
+  sources: &[Library<DefaultCtx>],
+  options: &MergeOptions,
+) -> anyhow::Result<MergeReport> {
+  let mut origin: HashMap<ArcKey, usize> = HashMap::new();
+  let mut resampled = Vec::new();
+  for (s, source) in sources.iter().enumerate() {
+    let units = Units::between(source, template);
+    for cell in source.cell.iter().filter(|c| !options.skips_cell(&c.name)) {
+      let template_cell = template.cell.get_mut(&cell.name).ok_or_else(|| {
+        anyhow::anyhow!("source {s}: cell {} not in the template", cell.name)
+      })?;
+      for pin in cell.pin.iter() {
+        let pin_name = pin.name.to_string();
+        let template_pin =
+          template_cell.pin.get_mut(pin.name.as_ref()).ok_or_else(|| {
+            anyhow::anyhow!(
+              "source {s}: pin {}/{pin_name} not in the template",
+              cell.name
+            )
+          })?;
+        let claim =
+          |key: ArcKey, origin: &mut HashMap<ArcKey, usize>| match origin.get(&key) {
+            None => Ok(origin.insert(key, s).is_none()),
+            Some(first) => match options.conflict {
+              Conflict::Error => anyhow::bail!("{key} is in sources {first} and {s}"),
+              Conflict::First => Ok(false),
+              Conflict::Last => Ok(origin.insert(key, s).is_some()),
+            },
+          };
+        if options.fields == Fields::Timing {
+          if !claim(ArcKey::of(&cell.name, &pin_name, None), &mut origin)? {
+            continue;
+          }
+          let skipped = |t: &Timing<DefaultCtx>| {
+            options.skips_arc(&cell.name, &pin_name, &t.related_pin.to_string())
+          };
+          let mut timing = pin.timing.clone();
+          timing.retain(|t| !skipped(t));
+          for t in timing.iter_mut() {
+            let template_timing = template_pin.timing.get(
+              t.related_pin.as_ref(),
+              t.timing_sense.as_ref(),
+              t.timing_type.as_ref(),
+              t.when.as_ref(),
+            );
+            let key = ArcKey::of(&cell.name, &pin_name, template_timing);
+            for (name, table, template_table) in [
+              (
+                "cell_rise",
+                &mut t.cell_rise,
+                template_timing.and_then(|t| t.cell_rise.as_ref()),
+              ),
+              (
+                "cell_fall",
+                &mut t.cell_fall,
+                template_timing.and_then(|t| t.cell_fall.as_ref()),
+              ),
+              (
+                "rise_transition",
+                &mut t.rise_transition,
+                template_timing.and_then(|t| t.rise_transition.as_ref()),
+              ),
+              (
+                "fall_transition",
+                &mut t.fall_transition,
+                template_timing.and_then(|t| t.fall_transition.as_ref()),
+              ),
+              (
+                "rise_constraint",
+                &mut t.rise_constraint,
+                template_timing.and_then(|t| t.rise_constraint.as_ref()),
+              ),
+              (
+                "fall_constraint",
+                &mut t.fall_constraint,
+                template_timing.and_then(|t| t.fall_constraint.as_ref()),
+              ),
+            ] {
+              let Some(source_table) = table.as_ref() else {
+                continue;
+              };
+              let (fitted, was_resampled) = units
+                .of(source, source_table)
+                .and_then(|factors| {
+                  fit(source_table, template_table, factors, options.grid)
+                })
+                .map_err(|e| anyhow::anyhow!("source {s}: arc {key}: {e}"))?;
+              if was_resampled {
+                resampled.push(format!("source {s}: {key}/{name}"));
+              }
+              *table = Some(fitted);
+            }
+          }
+          for t in template_pin.timing.iter().filter(|t| skipped(t)) {
+            timing.replace(t.clone());
+          }
+          template_pin.timing = timing;
+          continue;
+        }
+        for timing in pin.timing.iter() {
+          if options.skips_arc(&cell.name, &pin_name, &timing.related_pin.to_string()) {
+            continue;
+          }
+          let key = ArcKey::of(&cell.name, &pin_name, Some(timing));
+          let template_timing = template_pin
+            .timing
+            .get_mut(
+              timing.related_pin.as_ref(),
+              timing.timing_sense.as_ref(),
+              timing.timing_type.as_ref(),
+              timing.when.as_ref(),
+            )
+            .ok_or_else(|| {
+              anyhow::anyhow!("source {s}: arc {key} not in the template")
+            })?;
+          if !claim(key.clone(), &mut origin)? {
+            continue;
+          }
+          for (name, template_table, source_table) in [
+            ("cell_rise", &mut template_timing.cell_rise, &timing.cell_rise),
+            ("cell_fall", &mut template_timing.cell_fall, &timing.cell_fall),
+            (
+              "rise_transition",
+              &mut template_timing.rise_transition,
+              &timing.rise_transition,
+            ),
+            (
+              "fall_transition",
+              &mut template_timing.fall_transition,
+              &timing.fall_transition,
+            ),
+            (
+              "rise_constraint",
+              &mut template_timing.rise_constraint,
+              &timing.rise_constraint,
+            ),
+            (
+              "fall_constraint",
+              &mut template_timing.fall_constraint,
+              &timing.fall_constraint,
+            ),
+          ] {
+            let error = |e: anyhow::Error| anyhow::anyhow!("source {s}: arc {key}: {e}");
+            let fitted = match source_table {
+              None => None,
+              Some(source_table) => {
+                let (fitted, was_resampled) = units
+                  .of(source, source_table)
+                  .and_then(|factors| {
+                    fit(source_table, template_table.as_ref(), factors, options.grid)
+                  })
+                  .map_err(error)?;
+                if was_resampled {
+                  resampled.push(format!("source {s}: {key}/{name}"));
+                }
+                Some(fitted)
+              }
+            };
+            merge_table(options.fields, template_table, &fitted).map_err(error)?;
+          }
+        }
+      }
+    }
+  }
+  let mut kept = Vec::new();
+  for cell in template.cell.iter().filter(|c| !options.skips_cell(&c.name)) {
+    for pin in cell.pin.iter() {
+      let pin_name = pin.name.to_string();
+      if options.fields == Fields::Timing {
+        let key = ArcKey::of(&cell.name, &pin_name, None);
+        if !origin.contains_key(&key) {
+          kept.push(key.to_string());
+        }
+        continue;
+      }
+      for timing in pin.timing.iter() {
+        let key = ArcKey::of(&cell.name, &pin_name, Some(timing));
+        if !origin.contains_key(&key)
+          && !options.skips_arc(&cell.name, &pin_name, &timing.related_pin.to_string())
+        {
+          kept.push(key.to_string());
+        }
+      }
+    }
+  }
+  Ok(MergeReport { arcs: origin.len(), resampled, kept })
+}
+
+/// Merges the libraries at `sources` into the one at `template`, the latest
+/// source winning and mismatched grids resampled, and writes the result to `out`
+pub fn merge_files<P: AsRef<Path> + Sync>(
+  template: impl AsRef<Path>,
+  sources: &[P],
+  fields: Fields,
+  skip: Vec<Skip>,
+  out: impl AsRef<Path>,
+) -> anyhow::Result<MergeReport> {
+  let mut template_lib = crate::read_lib(template)?;
+  let sources = crate::parallel::read_libs(sources)?;
+  let options = MergeOptions {
+    fields,
+    conflict: Conflict::Last,
+    grid: GridPolicy::Resample,
+    skip,
+  };
+  let report = merge(&mut template_lib, &sources, &options)?;
+  let mut writer = BufWriter::new(File::create(out)?);
+  write!(&mut writer, "{}", template_lib)?;
+  Ok(report)
+}
+
+#[test]
+fn merge_options() -> anyhow::Result<()> {
+  let options = MergeOptions {
+    skip: vec!["HA1D1BWP30P140".parse()?, "ND2D1BWP30P140/ZN/A1".parse()?],
+    ..Default::default()
+  };
+  assert!(options.skips_cell("HA1D1BWP30P140"));
+  assert!(options.skips_arc("HA1D1BWP30P140", "CO", "A"));
+  assert!(options.skips_arc("ND2D1BWP30P140", "ZN", "A1"));
+  assert!(!options.skips_arc("ND2D1BWP30P140", "ZN", "A2"));
+  assert!("A/B".parse::<Skip>().is_err());
+  assert_eq!("ocv".parse::<Fields>()?, Fields::Ocv);
+  Ok(())
+}
+
+#[test]
+fn merge_keeps_unsourced_arcs() -> anyhow::Result<()> {
+  let lib = |cells: &[(&str, &str)]| {
+    let cells: String = cells
+      .iter()
+      .map(|(cell, values)| {
+        format!(
+          " cell ({cell}) {{\n pin (I) {{ direction : input; }}\n pin (ZN) {{\n \
+           direction : output;\n timing () {{\n related_pin : \"I\";\n \
+           timing_sense : negative_unate;\n timing_type : combinational;\n \
+           cell_rise (t2) {{ index_1 (\"0.1, 0.2\"); index_2 (\"0.01, 0.02\"); \
+           values ({values}); }}\n }}\n }}\n }}\n"
+        )
+      })
+      .collect();
+    Library::<DefaultCtx>::parse_lib(&format!(
+      "library (small) {{\n time_unit : \"1ns\";\n lu_table_template (t2) {{ \
+       variable_1 : input_net_transition; variable_2 : total_output_net_capacitance; \
+       }}\n{cells}}}\n"
+    ))
+    .map_err(|e| anyhow::anyhow!("{e:?}"))
+  };
+  let mut template = lib(&[
+    ("INVD1BWP30P140", "\"1, 2\", \"3, 4\""),
+    ("DFCNQD1BWP30P140", "\"5, 6\", \"7, 8\""),
+  ])?;
+  let source = lib(&[("INVD1BWP30P140", "\"9, 9\", \"9, 9\"")])?;
+  let report = merge(&mut template, &[source], &MergeOptions::default())?;
+  assert_eq!(report.arcs, 1);
+  assert_eq!(report.kept, ["DFCNQD1BWP30P140/ZN/I negative_unate combinational"]);
+  let cell_rise = |cell: &str| {
+    let pin = template.cell.get(cell).and_then(|c| c.pin.get("ZN".into()));
+    pin
+      .and_then(|p| p.timing.iter().next())
+      .and_then(|t| t.cell_rise.clone())
+  };
+  assert_eq!(cell_rise("INVD1BWP30P140").map(|t| t.values), Some(vec![9.0; 4]));
+  assert_eq!(
+    cell_rise("DFCNQD1BWP30P140").map(|t| t.values),
+    Some(vec![5.0, 6.0, 7.0, 8.0])
+  );
+  Ok(())
+}
+
+#[test]
+fn fit_lvf_grid() -> anyhow::Result<()> {
+  let lib = Library::<DefaultCtx>::parse_lib(
+    "library (small) {\n lu_table_template (delay) { variable_1 : input_net_transition; \
+     variable_2 : total_output_net_capacitance; }\n lu_table_template (hold) { \
+     variable_1 : constrained_pin_transition; variable_2 : related_pin_transition; }\n}\n",
+  )
+  .map_err(|e| anyhow::anyhow!("{e:?}"))?;
+  let units = Units { time: 1e-3, capacitance: 1e3 };
+  let table = |name: &str, index: Vec<f64>| TimingTableLookUp::<DefaultCtx> {
+    name: name.to_string(),
+    index_1: index.clone(),
+    index_2: index,
+    ..Default::default()
+  };
+  assert_eq!(units.of(&lib, &table("hold", vec![1.0]))?, [1e-3, 1e-3, 1e-3]);
+  assert_eq!(units.of(&lib, &table("delay", vec![1.0]))?, [1e-3, 1e3, 1e-3]);
+  assert_eq!(units.of(&lib, &table("scalar", Vec::new()))?, [1.0, 1.0, 1e-3]);
+  assert!(units.of(&lib, &table("t2", vec![1.0])).is_err());
+  // nominal x1 + x2 - 1 on the template grid, LVF values on a wider grid
+  let lvf = |mean: f64, std_dev: f64| LVFValue { mean, std_dev, skewness: 0.1 };
+  let source = TimingTableLookUp::<DefaultCtx> {
+    index_1: vec![1.0, 2.0],
+    index_2: vec![1.0, 2.0],
+    values: vec![1.0, 2.0, 2.0, 3.0],
+    lvf_index_1: vec![1.0, 3.0],
+    lvf_index_2: vec![1.0, 3.0],
+    lvf_values: vec![lvf(1.5, 2.0), lvf(3.5, 4.0), lvf(3.5, 4.0), lvf(5.5, 6.0)],
+    ..Default::default()
+  };
+  let template = TimingTableLookUp::<DefaultCtx> {
+    index_1: vec![1.0, 2.0],
+    index_2: vec![1.0, 2.0],
+    values: vec![0.0; 4],
+    ..Default::default()
+  };
+  assert!(fit(&source, Some(&template), [1.0; 3], GridPolicy::Reject).is_err());
+  let (fitted, resampled) =
+    fit(&source, Some(&template), [1.0; 3], GridPolicy::Resample)?;
+  assert!(resampled && fitted.lvf_index_1.is_empty());
+  assert_eq!(fitted.values, source.values);
+  let means: Vec<f64> = fitted.lvf_values.iter().map(|v| v.mean).collect();
+  let std_devs: Vec<f64> = fitted.lvf_values.iter().map(|v| v.std_dev).collect();
+  assert_eq!(means, [1.5, 2.5, 2.5, 3.5]);
+  assert_eq!(std_devs, [2.0, 3.0, 3.0, 4.0]);
+  let short = TimingTableLookUp { lvf_values: vec![lvf(1.5, 2.0)], ..source };
+  assert!(fit(&short, Some(&template), [1.0; 3], GridPolicy::Resample).is_err());
+  Ok(())
+}
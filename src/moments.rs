@@ -0,0 +1,327 @@
+//! Reader of the `<index>_moments.csv` files written by btdcell.
+//!
+//! Columns are located through the header, so their order does not matter and
+//! kurtosis is picked up when present. CRLF line endings, trailing commas and
+//! `nan`/`n/a`/`-`/empty cells are accepted; every other problem is reported
+//! as a [`MomentsError`] naming the file, line and column.
+//!
+//! A header may carry a time unit, as in `delay_mean(ps)` or `delay_std[ns]`,
+//! seconds otherwise. Mean and std dev are returned in seconds; skewness and
+//! kurtosis are standardised and must not carry a unit.
+use std::{
+  fmt, fs, io,
+  path::{Path, PathBuf},
+  str::FromStr,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quantity {
+  Delay,
+  Transition,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Moment {
+  Mean,
+  StdDev,
+  Skewness,
+  Kurtosis,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Moments {
+  pub mean: f64,
+  pub std_dev: f64,
+  pub skewness: f64,
+  pub kurtosis: Option<f64>,
+}
+
+impl Moments {
+  const NAN: Self = Self {
+    mean: f64::NAN,
+    std_dev: f64::NAN,
+    skewness: f64::NAN,
+    kurtosis: None,
+  };
+  fn set(&mut self, moment: Moment, value: f64) {
+    match moment {
+      Moment::Mean => self.mean = value,
+      Moment::StdDev => self.std_dev = value,
+      Moment::Skewness => self.skewness = value,
+      Moment::Kurtosis => self.kurtosis = Some(value),
+    }
+  }
+  /// mean, std dev and skewness are all numbers
+  pub fn is_complete(&self) -> bool {
+    !(self.mean.is_nan() || self.std_dev.is_nan() || self.skewness.is_nan())
+  }
+}
+
+/// Moments of one table point
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PointMoments {
+  pub delay: Moments,
+  pub transition: Moments,
+}
+
+#[derive(Debug)]
+pub enum ErrorKind {
+  Io(io::Error),
+  NoHeader,
+  NoData,
+  UnknownColumn(String),
+  DuplicateColumn(String),
+  MissingColumn(Quantity, Moment),
+  MissingValue,
+  BadValue(String),
+  UnknownUnit(String),
+  UnexpectedUnit(String),
+}
+
+#[derive(Debug)]
+pub struct MomentsError {
+  pub file: PathBuf,
+  /// 1-based, 0 when the error is not tied to a line
+  pub line: usize,
+  /// 1-based column and its header
+  pub column: Option<(usize, String)>,
+  pub kind: ErrorKind,
+}
+
+impl fmt::Display for MomentsError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.file.display())?;
+    if self.line != 0 {
+      write!(f, ":{}", self.line)?;
+    }
+    if let Some((column, header)) = &self.column {
+      write!(f, ": column {column} ({header})")?;
+    }
+    match &self.kind {
+      ErrorKind::Io(e) => write!(f, ": {e}"),
+      ErrorKind::NoHeader => write!(f, ": missing header line"),
+      ErrorKind::NoData => write!(f, ": missing data line"),
+      ErrorKind::UnknownColumn(s) => write!(f, ": unknown column {s:?}"),
+      ErrorKind::DuplicateColumn(s) => write!(f, ": duplicated column {s:?}"),
+      ErrorKind::MissingColumn(q, m) => write!(f, ": missing column {q:?} {m:?}"),
+      ErrorKind::MissingValue => write!(f, ": missing value"),
+      ErrorKind::BadValue(s) => write!(f, ": invalid number {s:?}"),
+      ErrorKind::UnknownUnit(s) => write!(f, ": unknown time unit {s:?}"),
+      ErrorKind::UnexpectedUnit(s) => write!(f, ": unit {s:?} on a unitless moment"),
+    }
+  }
+}
+
+impl std::error::Error for MomentsError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match &self.kind {
+      ErrorKind::Io(e) => Some(e),
+      _ => None,
+    }
+  }
+}
+
+fn classify(header: &str) -> Option<(Quantity, Moment)> {
+  let header = header.to_ascii_lowercase();
+  let quantity = if header.contains("delay") || header.contains("cell") {
+    Quantity::Delay
+  } else if header.contains("tran") || header.contains("slew") {
+    Quantity::Transition
+  } else {
+    return None;
+  };
+  let moment = if header.contains("mean") || header.contains("avg") {
+    Moment::Mean
+  } else if header.contains("std") || header.contains("sigma") {
+    Moment::StdDev
+  } else if header.contains("skew") {
+    Moment::Skewness
+  } else if header.contains("kurt") {
+    Moment::Kurtosis
+  } else {
+    return None;
+  };
+  Some((quantity, moment))
+}
+
+impl Moment {
+  /// mean and std dev carry the unit of the measured time, the others are standardised
+  pub fn has_time_unit(&self) -> bool {
+    matches!(self, Moment::Mean | Moment::StdDev)
+  }
+}
+
+/// Splits `delay_mean(ps)` into `delay_mean` and `ps`.
+pub(crate) fn split_unit(header: &str) -> (&str, Option<&str>) {
+  match header.rfind(['(', '[']) {
+    Some(open) => (
+      header[..open].trim(),
+      Some(header[open + 1..].trim_end_matches([')', ']']).trim()),
+    ),
+    None => (header, None),
+  }
+}
+
+/// Seconds per `unit`
+pub(crate) fn unit_scale(unit: &str) -> Option<f64> {
+  match unit.to_ascii_lowercase().as_str() {
+    "s" => Some(1.0),
+    "ms" => Some(1e-3),
+    "us" => Some(1e-6),
+    "ns" => Some(1e-9),
+    "ps" => Some(1e-12),
+    "fs" => Some(1e-15),
+    _ => None,
+  }
+}
+
+pub(crate) fn is_nan_marker(s: &str) -> bool {
+  s.is_empty()
+    || s == "-"
+    || s.eq_ignore_ascii_case("nan")
+    || s.eq_ignore_ascii_case("n/a")
+}
+
+/// Cells of one CSV line, without the line ending and the trailing empty cells
+/// past the first `columns`.
+pub(crate) fn cells(line: &str, columns: usize) -> Vec<&str> {
+  let mut cells: Vec<&str> =
+    line.trim_end_matches('\r').split(',').map(str::trim).collect();
+  while cells.len() > columns && cells.last().is_some_and(|s| s.is_empty()) {
+    cells.pop();
+  }
+  cells
+}
+
+pub fn parse_moments(file: &Path, text: &str) -> Result<PointMoments, MomentsError> {
+  let error = |line: usize, column: Option<(usize, String)>, kind: ErrorKind| {
+    MomentsError { file: file.to_path_buf(), line, column, kind }
+  };
+  let mut lines = text
+    .split('\n')
+    .enumerate()
+    .map(|(n, l)| (n + 1, l))
+    .filter(|(_, l)| !cells(l, 0).is_empty());
+  let (header_line, header) =
+    lines.next().ok_or_else(|| error(0, None, ErrorKind::NoHeader))?;
+  let headers = cells(header, 0);
+  let mut columns = Vec::with_capacity(headers.len());
+  let mut scales = Vec::with_capacity(headers.len());
+  for (idx, name) in headers.iter().enumerate() {
+    let column = Some((idx + 1, name.to_string()));
+    let (bare, unit) = split_unit(name);
+    let kind = classify(bare).ok_or_else(|| {
+      error(header_line, column.clone(), ErrorKind::UnknownColumn(name.to_string()))
+    })?;
+    match unit {
+      None => scales.push(1.0),
+      Some(unit) if !kind.1.has_time_unit() => {
+        return Err(error(
+          header_line,
+          column,
+          ErrorKind::UnexpectedUnit(unit.to_string()),
+        ))
+      }
+      Some(unit) => scales.push(unit_scale(unit).ok_or_else(|| {
+        error(header_line, column.clone(), ErrorKind::UnknownUnit(unit.to_string()))
+      })?),
+    }
+    if columns.contains(&kind) {
+      return Err(error(
+        header_line,
+        column,
+        ErrorKind::DuplicateColumn(name.to_string()),
+      ));
+    }
+    columns.push(kind);
+  }
+  for quantity in [Quantity::Delay, Quantity::Transition] {
+    for moment in [Moment::Mean, Moment::StdDev, Moment::Skewness] {
+      if !columns.contains(&(quantity, moment)) {
+        return Err(error(header_line, None, ErrorKind::MissingColumn(quantity, moment)));
+      }
+    }
+  }
+  let (line, data) = lines.next().ok_or_else(|| error(0, None, ErrorKind::NoData))?;
+  let values = cells(data, headers.len());
+  let mut point = PointMoments { delay: Moments::NAN, transition: Moments::NAN };
+  for (idx, ((quantity, moment), name)) in columns.iter().zip(headers.iter()).enumerate()
+  {
+    let scale = scales[idx];
+    let column = Some((idx + 1, name.to_string()));
+    let value = match values.get(idx) {
+      None if *moment == Moment::Kurtosis => continue,
+      None => return Err(error(line, column, ErrorKind::MissingValue)),
+      Some(s) if is_nan_marker(s) => f64::NAN,
+      Some(s) => {
+        f64::from_str(s)
+          .map_err(|_| error(line, column, ErrorKind::BadValue(s.to_string())))?
+          * scale
+      }
+    };
+    match quantity {
+      Quantity::Delay => point.delay.set(*moment, value),
+      Quantity::Transition => point.transition.set(*moment, value),
+    }
+  }
+  Ok(point)
+}
+
+pub fn read_moments(file: &Path) -> Result<PointMoments, MomentsError> {
+  let text = fs::read_to_string(file).map_err(|e| MomentsError {
+    file: file.to_path_buf(),
+    line: 0,
+    column: None,
+    kind: ErrorKind::Io(e),
+  })?;
+  parse_moments(file, &text)
+}
+
+#[test]
+fn moments_by_header() -> anyhow::Result<()> {
+  let file = Path::new("0_moments.csv");
+  let text =
+    "tran_mean,tran_std,tran_skew,delay_mean,delay_std,delay_skew,delay_kurt,\r\n\
+              2e-11,1e-12,0.5,1e-11,3e-13,NaN,3.1,\r\n";
+  let point = parse_moments(file, text)?;
+  assert_eq!(point.delay.mean, 1e-11);
+  assert_eq!(point.delay.kurtosis, Some(3.1));
+  assert!(point.delay.skewness.is_nan() && !point.delay.is_complete());
+  assert_eq!(point.transition.std_dev, 1e-12);
+  assert_eq!(point.transition.kurtosis, None);
+  // an empty last cell is a NaN marker, a missing one is not
+  let text = "delay_mean,delay_std,delay_skew,tran_mean,tran_std,tran_skew\n1,2,3,4,5,\n";
+  assert!(parse_moments(file, text)?.transition.skewness.is_nan());
+  let text = "delay_mean,delay_std,delay_skew,tran_mean,tran_std,tran_skew\n1,2,3,4,5\n";
+  let err = parse_moments(file, text).unwrap_err();
+  assert!(matches!(err.kind, ErrorKind::MissingValue));
+  let err = parse_moments(file, "delay_mean,delay_std\n1,2\n").unwrap_err();
+  assert!(matches!(
+    err.kind,
+    ErrorKind::MissingColumn(Quantity::Delay, Moment::Skewness)
+  ));
+  let text =
+    "delay_mean,delay_std,delay_skew,tran_mean,tran_std,tran_skew\n1,2,x,4,5,6\n";
+  let err = parse_moments(file, text).unwrap_err();
+  assert_eq!(
+    err.to_string(),
+    "0_moments.csv:2: column 3 (delay_skew): invalid number \"x\""
+  );
+  Ok(())
+}
+
+#[test]
+fn moments_units() -> anyhow::Result<()> {
+  let file = Path::new("0_moments.csv");
+  let text = "delay_mean(ps),delay_std[ns],delay_skew,tran_mean,tran_std,tran_skew\n\
+              12.5,0.002,0.3,1e-11,1e-12,-0.2\n";
+  let point = parse_moments(file, text)?;
+  assert!((point.delay.mean - 12.5e-12).abs() < 1e-24);
+  assert!((point.delay.std_dev - 2e-12).abs() < 1e-24);
+  assert_eq!(point.delay.skewness, 0.3);
+  let err = parse_moments(file, "delay_skew(ps)\n1\n").unwrap_err();
+  assert!(matches!(err.kind, ErrorKind::UnexpectedUnit(_)));
+  let err = parse_moments(file, "delay_mean(furlong)\n1\n").unwrap_err();
+  assert!(matches!(err.kind, ErrorKind::UnknownUnit(_)));
+  Ok(())
+}
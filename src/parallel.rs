@@ -0,0 +1,53 @@
+//! Thread pool for parsing independent libraries and processing independent cells.
+//!
+//! The thread count comes from `LVF_THREADS`, all cores when unset.
+use crate::read_lib;
+use liberty_db::{DefaultCtx, Library};
+use rayon::prelude::*;
+use std::path::Path;
+
+pub const THREADS_VAR: &str = "LVF_THREADS";
+
+/// Threads to use, `0` leaving the choice to rayon
+pub fn threads() -> anyhow::Result<usize> {
+  match std::env::var(THREADS_VAR) {
+    Ok(threads) => threads
+      .parse()
+      .map_err(|e| anyhow::anyhow!("{THREADS_VAR}={threads}: {e}")),
+    Err(_) => Ok(0),
+  }
+}
+
+pub fn pool(threads: usize) -> anyhow::Result<rayon::ThreadPool> {
+  Ok(rayon::ThreadPoolBuilder::new().num_threads(threads).build()?)
+}
+
+/// Runs `op` on a pool of [`threads`] threads
+pub fn install<T: Send>(op: impl FnOnce() -> T + Send) -> anyhow::Result<T> {
+  Ok(pool(threads()?)?.install(op))
+}
+
+/// [`read_lib`] of every path, in the order of `paths`
+pub fn read_libs<P: AsRef<Path> + Sync>(
+  paths: &[P],
+) -> anyhow::Result<Vec<Library<DefaultCtx>>> {
+  install(|| paths.par_iter().map(read_lib).collect())?
+}
+
+/// `op` on its own clone of `template` for each item, in the order of `items`
+pub fn map_cloned<I: Sync, T: Send>(
+  template: &Library<DefaultCtx>,
+  items: &[I],
+  op: impl Fn(&I, Library<DefaultCtx>) -> anyhow::Result<T> + Sync,
+) -> anyhow::Result<Vec<T>> {
+  install(|| items.par_iter().map(|item| op(item, template.clone())).collect())?
+}
+
+#[test]
+fn parallel_order() -> anyhow::Result<()> {
+  let squares =
+    pool(2)?.install(|| (0..64_u64).into_par_iter().map(|i| i * i).collect::<Vec<_>>());
+  assert_eq!(squares, (0..64_u64).map(|i| i * i).collect::<Vec<_>>());
+  assert!(read_libs(&["/nonexistent.lib"]).is_err());
+  Ok(())
+}
@@ -0,0 +1,218 @@
+//! Physical plausibility of the collected moments of one table.
+//!
+//! Along each axis the std dev of a real arc only grows or only shrinks, and its
+//! ratio to the nominal value changes smoothly between neighbouring points.
+//! Setting `LVF_STRICT` to anything but empty or `0` makes violations fatal.
+use anyhow::bail;
+use liberty_db::{timing::TimingTableLookUp, DefaultCtx};
+use serde::Serialize;
+use std::fmt;
+
+pub const STRICT_VAR: &str = "LVF_STRICT";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlausibilityOptions {
+  /// largest |mean shift| as a fraction of the nominal value
+  pub max_shift_ratio: f64,
+  pub skewness: (f64, f64),
+  /// largest factor between the sigma/nominal ratios of neighbouring points
+  pub max_ratio_jump: f64,
+  /// fail the collection on any violation
+  pub strict: bool,
+}
+
+impl Default for PlausibilityOptions {
+  fn default() -> Self {
+    Self {
+      max_shift_ratio: 0.5,
+      skewness: (-2.0, 2.0),
+      max_ratio_jump: 2.0,
+      strict: false,
+    }
+  }
+}
+
+impl PlausibilityOptions {
+  /// The defaults, strict as `LVF_STRICT` asks
+  pub fn from_env() -> Self {
+    let strict = std::env::var(STRICT_VAR).is_ok_and(|v| !v.is_empty() && v != "0");
+    Self { strict, ..Default::default() }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Check {
+  NonPositiveStdDev,
+  MeanShift,
+  Skewness,
+  /// along `index_1`, the input slew
+  NonMonotonicSlew,
+  /// along `index_2`, the output load
+  NonMonotonicLoad,
+  RatioJump,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Violation {
+  pub check: Check,
+  /// `(index_1, index_2)` position of the point
+  pub index: (usize, usize),
+  pub value: f64,
+}
+
+impl fmt::Display for Violation {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let (i1, i2) = self.index;
+    write!(f, "{:?} at ({i1}, {i2}): {}", self.check, self.value)
+  }
+}
+
+/// Whether `values` only grow or only shrink, `None` when they do, otherwise the
+/// position where the direction turns
+fn turn(values: &[f64]) -> Option<usize> {
+  let mut direction = 0.0;
+  for i in 1..values.len() {
+    let step = values[i] - values[i - 1];
+    if step == 0.0 {
+      continue;
+    }
+    if direction * step < 0.0 {
+      return Some(i);
+    }
+    direction = step.signum();
+  }
+  None
+}
+
+/// Violations of the LVF values of `table`
+pub fn check_table(
+  table: &TimingTableLookUp<DefaultCtx>,
+  options: &PlausibilityOptions,
+) -> Vec<Violation> {
+  let size2 = table.index_2.len().max(1);
+  let size1 = table.values.len() / size2;
+  if table.lvf_values.len() != table.values.len() {
+    return Vec::new();
+  }
+  let at = |i: usize| (i / size2, i % size2);
+  let mut out = Vec::new();
+  for (i, (lvf, nominal)) in table.lvf_values.iter().zip(table.values.iter()).enumerate()
+  {
+    let index = at(i);
+    if lvf.std_dev.is_nan() || lvf.std_dev <= 0.0 {
+      out.push(Violation {
+        check: Check::NonPositiveStdDev,
+        index,
+        value: lvf.std_dev,
+      });
+    }
+    let shift = lvf.mean - nominal;
+    if shift.is_nan() || shift.abs() > options.max_shift_ratio * nominal.abs() {
+      out.push(Violation {
+        check: Check::MeanShift,
+        index,
+        value: shift / nominal,
+      });
+    }
+    let (lo, hi) = options.skewness;
+    if !(lo..=hi).contains(&lvf.skewness) {
+      out.push(Violation { check: Check::Skewness, index, value: lvf.skewness });
+    }
+  }
+  let std_dev = |i1: usize, i2: usize| table.lvf_values[i1 * size2 + i2].std_dev;
+  for i1 in 0..size1 {
+    let row: Vec<f64> = (0..size2).map(|i2| std_dev(i1, i2)).collect();
+    if let Some(i2) = turn(&row) {
+      out.push(Violation {
+        check: Check::NonMonotonicLoad,
+        index: (i1, i2),
+        value: row[i2],
+      });
+    }
+  }
+  for i2 in 0..size2 {
+    let column: Vec<f64> = (0..size1).map(|i1| std_dev(i1, i2)).collect();
+    if let Some(i1) = turn(&column) {
+      out.push(Violation {
+        check: Check::NonMonotonicSlew,
+        index: (i1, i2),
+        value: column[i1],
+      });
+    }
+  }
+  let ratio = |i: usize| table.lvf_values[i].std_dev / table.values[i];
+  for i in 0..table.values.len() {
+    let (i1, i2) = at(i);
+    let neighbours =
+      [(i1 + 1 < size1).then(|| i + size2), (i2 + 1 < size2).then(|| i + 1)];
+    for j in neighbours.into_iter().flatten() {
+      let (a, b) = (ratio(i), ratio(j));
+      if a.is_nan() || b.is_nan() || a <= 0.0 || b <= 0.0 {
+        continue;
+      }
+      let jump = a.max(b) / a.min(b);
+      if jump > options.max_ratio_jump {
+        out.push(Violation { check: Check::RatioJump, index: at(j), value: jump });
+      }
+    }
+  }
+  out
+}
+
+/// Fails on any of `violations` in strict mode
+pub fn enforce(
+  what: &str,
+  violations: &[Violation],
+  options: &PlausibilityOptions,
+) -> anyhow::Result<()> {
+  if options.strict && !violations.is_empty() {
+    let list: Vec<String> = violations.iter().map(ToString::to_string).collect();
+    bail!("{what}: implausible statistics: {}", list.join("; "));
+  }
+  Ok(())
+}
+
+#[test]
+fn plausibility_checks() {
+  use liberty_db::timing::LVFValue;
+  let nominal = vec![1.0, 2.0, 2.0, 4.0];
+  let lvf = |std_dev: [f64; 4], skewness: f64| -> Vec<LVFValue> {
+    nominal
+      .iter()
+      .zip(std_dev)
+      .map(|(n, std_dev)| LVFValue { mean: n * 1.01, std_dev, skewness })
+      .collect()
+  };
+  let mut table = TimingTableLookUp::<DefaultCtx> {
+    index_1: vec![0.1, 0.2],
+    index_2: vec![1.0, 2.0],
+    size1: 2,
+    size2: 2,
+    values: nominal.clone(),
+    lvf_values: lvf([0.1, 0.2, 0.2, 0.4], 0.1),
+    ..Default::default()
+  };
+  let options = PlausibilityOptions::default();
+  assert_eq!(check_table(&table, &options), []);
+  assert_eq!(turn(&[1.0, 2.0, 2.0, 3.0]), None);
+  assert_eq!(turn(&[1.0, 2.0, 1.5]), Some(2));
+  table.lvf_values = lvf([0.1, 0.05, 0.2, 0.0], 3.0);
+  table.lvf_values[1].mean = 3.5;
+  let checks: Vec<(Check, (usize, usize))> = check_table(&table, &options)
+    .iter()
+    .map(|v| (v.check, v.index))
+    .collect();
+  for expected in [
+    (Check::Skewness, (0, 0)),
+    (Check::MeanShift, (0, 1)),
+    (Check::NonPositiveStdDev, (1, 1)),
+    (Check::RatioJump, (0, 1)),
+  ] {
+    assert!(checks.contains(&expected), "{expected:?} in {checks:?}");
+  }
+  let strict = PlausibilityOptions { strict: true, ..options };
+  let violations = check_table(&table, &strict);
+  assert!(enforce("INV arc01 cell_rise", &violations, &strict).is_err());
+  assert!(enforce("INV arc01 cell_rise", &violations, &options).is_ok());
+}
@@ -0,0 +1,225 @@
+//! POCV coefficient files for signoff runs that do not read LVF.
+//!
+//! The coefficient of a point is `std_dev / nominal` of its delay table; the
+//! points inside a slew/load [`Region`] are reduced into one coefficient per
+//! cell or per arc. Arcs that differ only in `when` share a coefficient.
+//! The file follows the PrimeTime side file layout:
+//! ``` text
+//! version: 1.0
+//!
+//! object_type: lib_timing_arcs
+//! object_spec: LIB/CELL
+//! from_pin: I
+//! to_pin: ZN
+//! rise_fall: rise
+//! coefficient: 0.0213
+//! ```
+use liberty_db::{timing::TimingTableLookUp, DefaultCtx, Library};
+use std::{collections::BTreeMap, fmt, io::Write, str::FromStr};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Granularity {
+  #[default]
+  Cell,
+  Arc,
+}
+
+/// How the points of a region become one coefficient
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Reduce {
+  #[default]
+  Mean,
+  Max,
+  Median,
+}
+
+impl FromStr for Reduce {
+  type Err = anyhow::Error;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "mean" => Ok(Reduce::Mean),
+      "max" => Ok(Reduce::Max),
+      "median" => Ok(Reduce::Median),
+      _ => anyhow::bail!("unknown reduction {s:?}"),
+    }
+  }
+}
+
+/// Inclusive `index_1` (slew) and `index_2` (load) windows, the whole table when `None`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Region {
+  pub index_1: Option<(f64, f64)>,
+  pub index_2: Option<(f64, f64)>,
+}
+
+impl Region {
+  fn contains(&self, x1: f64, x2: Option<f64>) -> bool {
+    let inside = |window: Option<(f64, f64)>, x: f64| {
+      window.is_none_or(|(lo, hi)| lo <= x && x <= hi)
+    };
+    inside(self.index_1, x1) && x2.is_none_or(|x2| inside(self.index_2, x2))
+  }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PocvOptions {
+  pub granularity: Granularity,
+  pub reduce: Reduce,
+  pub region: Region,
+}
+
+/// `(from_pin, to_pin, rise_fall)`
+pub type ArcKey = (String, String, &'static str);
+
+/// One block of the coefficient file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Coefficient {
+  pub cell: String,
+  pub arc: Option<ArcKey>,
+  pub coefficient: f64,
+}
+
+/// `std_dev / nominal` of the points of `table` inside `region`
+fn ratios(table: &TimingTableLookUp<DefaultCtx>, region: &Region) -> Vec<f64> {
+  let size2 = table.index_2.len().max(1);
+  table
+    .values
+    .iter()
+    .zip(table.lvf_values.iter())
+    .enumerate()
+    .filter(|(idx, (nominal, _))| {
+      let x1 = table.index_1.get(idx / size2).copied().unwrap_or_default();
+      **nominal > 0.0 && region.contains(x1, table.index_2.get(idx % size2).copied())
+    })
+    .map(|(_, (nominal, lvf))| lvf.std_dev / nominal)
+    .collect()
+}
+
+fn reduce(how: Reduce, values: Vec<f64>) -> Option<f64> {
+  if values.is_empty() {
+    return None;
+  }
+  Some(match how {
+    Reduce::Mean => values.iter().sum::<f64>() / values.len() as f64,
+    Reduce::Max => values.iter().copied().fold(f64::MIN, f64::max),
+    Reduce::Median => return median(values),
+  })
+}
+
+pub(crate) fn median(mut values: Vec<f64>) -> Option<f64> {
+  values.sort_by(f64::total_cmp);
+  let mid = values.len() / 2;
+  if values.is_empty() {
+    None
+  } else if values.len().is_multiple_of(2) {
+    Some((values[mid - 1] + values[mid]) / 2.0)
+  } else {
+    Some(values[mid])
+  }
+}
+
+/// Coefficients of every cell or arc with LVF delay tables, in name order
+pub fn coefficients(
+  lib: &Library<DefaultCtx>,
+  options: &PocvOptions,
+) -> Vec<Coefficient> {
+  let mut groups: BTreeMap<(String, Option<ArcKey>), Vec<f64>> = BTreeMap::new();
+  for cell in lib.cell.iter() {
+    for pin in cell.pin.iter() {
+      for timing in pin.timing.iter() {
+        for (table, rise_fall) in
+          [(&timing.cell_rise, "rise"), (&timing.cell_fall, "fall")]
+        {
+          let Some(table) = table.as_ref().filter(|t| !t.lvf_values.is_empty()) else {
+            continue;
+          };
+          let arc = match options.granularity {
+            Granularity::Cell => None,
+            Granularity::Arc => {
+              Some((timing.related_pin.to_string(), pin.name.to_string(), rise_fall))
+            }
+          };
+          groups
+            .entry((cell.name.clone(), arc))
+            .or_default()
+            .extend(ratios(table, &options.region));
+        }
+      }
+    }
+  }
+  groups
+    .into_iter()
+    .filter_map(|((cell, arc), values)| {
+      let coefficient = reduce(options.reduce, values)?;
+      Some(Coefficient { cell, arc, coefficient })
+    })
+    .collect()
+}
+
+struct Block<'a>(&'a str, &'a Coefficient);
+
+impl fmt::Display for Block<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let Block(lib_name, c) = self;
+    match &c.arc {
+      None => writeln!(f, "object_type: lib_cell")?,
+      Some(_) => writeln!(f, "object_type: lib_timing_arcs")?,
+    }
+    writeln!(f, "object_spec: {lib_name}/{}", c.cell)?;
+    if let Some((from, to, rise_fall)) = &c.arc {
+      writeln!(f, "from_pin: {from}")?;
+      writeln!(f, "to_pin: {to}")?;
+      writeln!(f, "rise_fall: {rise_fall}")?;
+    }
+    writeln!(f, "coefficient: {:.6}", c.coefficient)
+  }
+}
+
+pub fn write_pocv(
+  writer: &mut impl Write,
+  lib_name: &str,
+  coefficients: &[Coefficient],
+) -> std::io::Result<()> {
+  writeln!(writer, "version: 1.0")?;
+  for c in coefficients {
+    write!(writer, "\n{}", Block(lib_name, c))?;
+  }
+  Ok(())
+}
+
+#[test]
+fn pocv_reduce() {
+  assert_eq!(reduce(Reduce::Mean, vec![0.01, 0.03]), Some(0.02));
+  assert_eq!(reduce(Reduce::Max, vec![0.01, 0.03, 0.02]), Some(0.03));
+  assert_eq!(reduce(Reduce::Median, vec![0.04, 0.01, 0.03]), Some(0.03));
+  assert_eq!(reduce(Reduce::Mean, vec![]), None);
+  let region = Region { index_1: Some((0.0, 0.1)), index_2: None };
+  assert!(region.contains(0.1, Some(5.0)) && !region.contains(0.2, None));
+  let c = Coefficient {
+    cell: "INVD1".into(),
+    arc: Some(("I".into(), "ZN".into(), "fall")),
+    coefficient: 0.02,
+  };
+  let mut out = Vec::new();
+  write_pocv(&mut out, "lib", &[c]).unwrap();
+  assert_eq!(
+    String::from_utf8(out).unwrap(),
+    "version: 1.0\n\nobject_type: lib_timing_arcs\nobject_spec: lib/INVD1\n\
+     from_pin: I\nto_pin: ZN\nrise_fall: fall\ncoefficient: 0.020000\n"
+  );
+}
+
+#[test]
+fn pocv_file() -> anyhow::Result<()> {
+  let lib_path = "pruned_active_lvf_0503.lib";
+  let lib = crate::read_lib(lib_path)?;
+  for (granularity, out_path) in [
+    (Granularity::Cell, "pruned_active_lvf_0503.cell.pocv"),
+    (Granularity::Arc, "pruned_active_lvf_0503.arc.pocv"),
+  ] {
+    let options = PocvOptions { granularity, ..Default::default() };
+    let mut writer = std::io::BufWriter::new(std::fs::File::create(out_path)?);
+    write_pocv(&mut writer, &lib.name, &coefficients(&lib, &options))?;
+  }
+  Ok(())
+}
@@ -0,0 +1,451 @@
+//! LVF tables of the drive strengths without Monte Carlo results.
+//!
+//! For every arc of [`INFO`], the characterized cells of the family give, point by
+//! point, sigma/nominal, mean shift/nominal and skewness against drive strength.
+//! The sigma ratio follows a power law in the drive fitted in log-log, or with
+//! a single characterized drive the Pelgrom exponent of
+//! [`PredictOptions::single_drive_exponent`]; shift ratio and skewness are
+//! interpolated in log drive and held beyond the characterized range. The other
+//! drives of the family get these applied to their own nominal tables, position by
+//! position, and a `provenance: predicted` comment.
+use crate::{
+  arcs::{arc_timing_type, lvf_tables, push_comment, ArcInfo, INFO},
+  compare::lvf_index,
+  drive_of,
+  provenance::{self, Provenance},
+  CELL_GROUP,
+};
+use anyhow::{bail, Context as _};
+use liberty_db::{
+  timing::{items::LVFValue, TimingTableLookUp},
+  DefaultCtx, Library,
+};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Drive taken for D0 cells, whose drive strength parses as 0
+pub const MIN_DRIVE: f64 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PredictOptions {
+  /// d(ln sigma/nominal) / d(ln drive) when a single drive is characterized
+  pub single_drive_exponent: f64,
+}
+
+impl Default for PredictOptions {
+  fn default() -> Self {
+    Self { single_drive_exponent: -0.5 }
+  }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PredictReport {
+  pub predicted: Vec<Provenance>,
+  /// `cell arcN: reason` of the arcs left as they were
+  pub skipped: Vec<String>,
+}
+
+/// One point of one characterized cell, relative to its nominal value
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Observation {
+  /// ln drive
+  x: f64,
+  ratio: f64,
+  shift: f64,
+  skewness: f64,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Model {
+  shape: (usize, usize),
+  /// observations of each point, sorted by drive
+  points: Vec<Vec<Observation>>,
+}
+
+fn log_drive(cell: &str) -> Option<f64> {
+  drive_of(cell).map(|drive| drive.max(MIN_DRIVE).ln())
+}
+
+fn shape(table: &TimingTableLookUp<DefaultCtx>) -> (usize, usize) {
+  (table.index_1.len().max(1), table.index_2.len().max(1))
+}
+
+/// Observations of the points of one table, `None` where the nominal value is zero
+type Observed = ((usize, usize), Vec<Option<Observation>>);
+
+impl Model {
+  /// Observations of `table` at drive `x`, checked to fit the model
+  fn observe(
+    &self,
+    x: f64,
+    table: &TimingTableLookUp<DefaultCtx>,
+  ) -> anyhow::Result<Observed> {
+    if !self.points.is_empty() && shape(table) != self.shape {
+      bail!("{:?} table, {:?} before", shape(table), self.shape);
+    }
+    // LVF values on their own grid are not point by point with the nominal ones
+    if lvf_index(&table.lvf_index_1, &table.index_1) != table.index_1
+      || lvf_index(&table.lvf_index_2, &table.index_2) != table.index_2
+    {
+      bail!("LVF grid differs from the nominal grid");
+    }
+    let observed = table
+      .values
+      .iter()
+      .zip(table.lvf_values.iter())
+      .map(|(nominal, lvf)| {
+        (*nominal != 0.0).then(|| Observation {
+          x,
+          ratio: lvf.std_dev / nominal.abs(),
+          shift: (lvf.mean - nominal) / nominal,
+          skewness: lvf.skewness,
+        })
+      })
+      .collect();
+    Ok((shape(table), observed))
+  }
+  fn add(&mut self, (shape, observed): Observed) {
+    if self.points.is_empty() {
+      self.shape = shape;
+      self.points = vec![Vec::new(); observed.len()];
+    }
+    for (points, observation) in self.points.iter_mut().zip(observed) {
+      if let Some(observation) = observation {
+        points.push(observation);
+        points.sort_by(|a, b| a.x.total_cmp(&b.x));
+      }
+    }
+  }
+}
+
+/// sigma/nominal at `x` from a least-squares line through `(x, ln ratio)`
+fn power_law(observations: &[Observation], x: f64, exponent: f64) -> Option<f64> {
+  let points: Vec<(f64, f64)> = observations
+    .iter()
+    .filter(|o| o.ratio > 0.0)
+    .map(|o| (o.x, o.ratio.ln()))
+    .collect();
+  if points.is_empty() {
+    return None;
+  }
+  let n = points.len() as f64;
+  let mx = points.iter().map(|p| p.0).sum::<f64>() / n;
+  let my = points.iter().map(|p| p.1).sum::<f64>() / n;
+  let sxx: f64 = points.iter().map(|p| (p.0 - mx).powi(2)).sum();
+  let slope = if sxx > 1e-12 {
+    points.iter().map(|p| (p.0 - mx) * (p.1 - my)).sum::<f64>() / sxx
+  } else {
+    exponent
+  };
+  Some((my + slope * (x - mx)).exp())
+}
+
+/// `value` of `observations` linear in `x`, held at both ends
+fn interpolate(
+  observations: &[Observation],
+  x: f64,
+  value: impl Fn(&Observation) -> f64,
+) -> Option<f64> {
+  let (first, last) = (observations.first()?, observations.last()?);
+  if x <= first.x {
+    return Some(value(first));
+  }
+  if x >= last.x {
+    return Some(value(last));
+  }
+  let upper = observations.iter().position(|o| o.x >= x)?;
+  let (a, b) = (&observations[upper - 1], &observations[upper]);
+  let t = if b.x > a.x { (x - a.x) / (b.x - a.x) } else { 0.0 };
+  Some(value(a) + t * (value(b) - value(a)))
+}
+
+/// LVF values of `table` at drive `x` from `model`
+fn predict_table(
+  model: &Model,
+  x: f64,
+  table: &mut TimingTableLookUp<DefaultCtx>,
+  options: &PredictOptions,
+) -> anyhow::Result<()> {
+  if shape(table) != model.shape || table.values.len() != model.points.len() {
+    bail!("{:?} table, characterized {:?}", shape(table), model.shape);
+  }
+  let mut lvf_values = Vec::with_capacity(table.values.len());
+  for (i, (nominal, observations)) in
+    table.values.iter().zip(model.points.iter()).enumerate()
+  {
+    let predicted = (
+      power_law(observations, x, options.single_drive_exponent),
+      interpolate(observations, x, |o| o.shift),
+      interpolate(observations, x, |o| o.skewness),
+    );
+    let (Some(ratio), Some(shift), Some(skewness)) = predicted else {
+      bail!("no characterized value at point {i}");
+    };
+    lvf_values.push(LVFValue {
+      mean: nominal * (1.0 + shift),
+      std_dev: ratio * nominal.abs(),
+      skewness,
+    });
+  }
+  table.lvf_values = lvf_values;
+  table.lvf_index_1.clear();
+  table.lvf_index_2.clear();
+  Ok(())
+}
+
+/// Tables of arc `info` in `target`, predicted from `models` of the delay and the
+/// transition table, with the provenance of each
+fn predict_arc(
+  lib: &mut Library<DefaultCtx>,
+  target: &str,
+  info: ArcInfo,
+  models: &[Model; 2],
+  provenance: &Provenance,
+  options: &PredictOptions,
+) -> anyhow::Result<[Provenance; 2]> {
+  let (_, _, pin_name, related_pin, _, when, is_rise, timing_sense) = info;
+  let x = log_drive(target).with_context(|| format!("no drive strength in {target}"))?;
+  let cell = lib.cell.get_mut(target).with_context(|| format!("cell {target}"))?;
+  let when = if when.is_empty() { None } else { Some(cell.parse_logic_boolexpr(when)?) };
+  let pin = cell
+    .pin
+    .get_mut(pin_name.into())
+    .with_context(|| format!("pin {pin_name}"))?;
+  let timing_type =
+    arc_timing_type(pin.timing.iter(), related_pin, timing_sense, when.as_ref())
+      .context("timing")?;
+  let mut timing = pin
+    .timing
+    .take(related_pin.into(), Some(&timing_sense), timing_type.as_ref(), when.as_ref())
+    .context("timing")?;
+  let (names, tables) = if is_rise {
+    (
+      ["cell_rise", "rise_transition"],
+      [&mut timing.cell_rise, &mut timing.rise_transition],
+    )
+  } else {
+    (
+      ["cell_fall", "fall_transition"],
+      [&mut timing.cell_fall, &mut timing.fall_transition],
+    )
+  };
+  // predict both tables before touching either
+  let mut predicted = Vec::with_capacity(2);
+  let result =
+    tables
+      .iter()
+      .zip(models)
+      .zip(names)
+      .try_for_each(|((table, model), name)| {
+        let mut table = table
+          .as_ref()
+          .cloned()
+          .with_context(|| format!("missing {name} table"))?;
+        predict_table(model, x, &mut table, options).with_context(|| name.to_string())?;
+        let provenance = Provenance { table: name.to_string(), ..provenance.clone() };
+        push_comment(&mut table.comments, &provenance.comment());
+        predicted.push((table, provenance));
+        Ok::<_, anyhow::Error>(())
+      });
+  let mut provenances = Vec::with_capacity(2);
+  if result.is_ok() {
+    for (table, (new, provenance)) in tables.into_iter().zip(predicted) {
+      *table = Some(new);
+      provenances.push(provenance);
+    }
+  }
+  pin.timing.insert(timing);
+  result?;
+  Ok(provenances.try_into().expect("two tables"))
+}
+
+/// OCV tables of the uncharacterized drives in `lib`, predicted from the drives
+/// `INFO` characterized
+pub fn predict(lib: &mut Library<DefaultCtx>, options: &PredictOptions) -> PredictReport {
+  // characterized cells of each arc, the cell left out of the key
+  let mut arcs: BTreeMap<_, (ArcInfo, Vec<&'static str>)> = BTreeMap::new();
+  for info in INFO {
+    let (family, cell, pin, related_pin, arc_num, when, is_rise, _) = info;
+    let key = (family, pin, related_pin, arc_num, when, is_rise);
+    arcs.entry(key).or_insert((info, Vec::new())).1.push(cell);
+  }
+  let mut report = PredictReport::default();
+  for (info, characterized) in arcs.into_values() {
+    let (family, _, pin_name, related_pin, arc_num, when, is_rise, timing_sense) = info;
+    let mut models = [Model::default(), Model::default()];
+    let mut from = Vec::new();
+    for cell in characterized.iter().copied() {
+      if lib.cell.get(cell).is_none() {
+        continue;
+      }
+      let learned = log_drive(cell)
+        .with_context(|| format!("no drive strength in {cell}"))
+        .and_then(|x| {
+          let info =
+            (family, cell, pin_name, related_pin, arc_num, when, is_rise, timing_sense);
+          let tables = lvf_tables(info, lib)?;
+          // both tables or neither, so a skipped cell leaves no trace in the models
+          let observed = models
+            .iter()
+            .zip(tables)
+            .map(|(model, (name, table))| {
+              model.observe(x, table).with_context(|| name.to_string())
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+          for (model, observed) in models.iter_mut().zip(observed) {
+            model.add(observed);
+          }
+          Ok(())
+        });
+      match learned {
+        Ok(()) => from.push(cell.to_string()),
+        Err(e) => report.skipped.push(format!("{cell} arc{arc_num}: {e:#}")),
+      }
+    }
+    if from.is_empty() {
+      continue;
+    }
+    let Some((_, _, family_cells)) =
+      CELL_GROUP.iter().find(|(name, _, _)| *name == family)
+    else {
+      continue;
+    };
+    let targets: Vec<&str> = family_cells
+      .iter()
+      .copied()
+      .filter(|cell| !characterized.contains(cell) && lib.cell.get(cell).is_some())
+      .collect();
+    let timing = if when.is_empty() {
+      related_pin.to_string()
+    } else {
+      format!("{related_pin} when {when}")
+    };
+    for target in targets {
+      let provenance = Provenance {
+        cell: target.to_string(),
+        pin: pin_name.to_string(),
+        timing: timing.clone(),
+        table: String::new(),
+        arc: arc_num.to_string(),
+        run: None,
+        collected: provenance::now(),
+        sources: BTreeMap::new(),
+        predicted_from: from.clone(),
+      };
+      match predict_arc(lib, target, info, &models, &provenance, options) {
+        Ok(tables) => report.predicted.extend(tables),
+        Err(e) => report.skipped.push(format!("{target} arc{arc_num}: {e:#}")),
+      }
+    }
+  }
+  report
+}
+
+#[test]
+fn predict_drive() -> anyhow::Result<()> {
+  let table = |name: &str, values: &str| {
+    format!(
+      "{name} (t2) {{\n index_1 (\"0.1, 0.2\");\n index_2 (\"0.01, 0.02\");\n \
+       values ({values});\n }}\n"
+    )
+  };
+  let lvf = |name: &str| {
+    [
+      table(&format!("ocv_mean_shift_{name}"), "\"0.01, 0.02\", \"0.03, 0.04\""),
+      table(&format!("ocv_std_dev_{name}"), "\"0.1, 0.2\", \"0.3, 0.4\""),
+      table(&format!("ocv_skewness_{name}"), "\"0.2, 0.2\", \"0.2, 0.2\""),
+    ]
+    .concat()
+  };
+  let cell = |name: &str, ocv: bool| {
+    let nominal = "\"1, 2\", \"3, 4\"";
+    let mut tables =
+      [table("cell_rise", nominal), table("rise_transition", nominal)].concat();
+    if ocv {
+      tables.push_str(&lvf("cell_rise"));
+      tables.push_str(&lvf("rise_transition"));
+    }
+    format!(
+      "cell ({name}) {{\n pin (I) {{ direction : input; }}\n pin (ZN) {{\n \
+       direction : output;\n function : \"!I\";\n timing () {{\n related_pin : \"I\";\n \
+       timing_sense : negative_unate;\n timing_type : combinational;\n{tables} }}\n }}\n }}\n"
+    )
+  };
+  let text = format!(
+    "library (small) {{\n delay_model : table_lookup;\n time_unit : \"1ns\";\n \
+     lu_table_template (t2) {{\n variable_1 : input_net_transition;\n \
+     variable_2 : total_output_net_capacitance;\n index_1 (\"0.1, 0.2\");\n \
+     index_2 (\"0.01, 0.02\");\n }}\n{}{}}}\n",
+    cell("INVD1BWP30P140", true),
+    cell("INVD4BWP30P140", false)
+  );
+  let mut lib =
+    Library::<DefaultCtx>::parse_lib(&text).map_err(|e| anyhow::anyhow!("{e:?}"))?;
+  let report = predict(&mut lib, &PredictOptions::default());
+  let predicted: Vec<(&str, &str)> = report
+    .predicted
+    .iter()
+    .map(|p| (p.cell.as_str(), p.table.as_str()))
+    .collect();
+  assert_eq!(
+    predicted,
+    [("INVD4BWP30P140", "cell_rise"), ("INVD4BWP30P140", "rise_transition")]
+  );
+  assert!(report.skipped.iter().any(|s| s.starts_with("INVD1BWP30P140 arc02")));
+  let timing = lib
+    .cell
+    .get("INVD4BWP30P140")
+    .and_then(|cell| cell.pin.get("ZN".into()))
+    .and_then(|pin| pin.timing.iter().next())
+    .context("timing")?;
+  let cell_rise = timing.cell_rise.as_ref().context("cell_rise")?;
+  // sigma/nominal 0.1 at D1 halves at D4
+  let std_dev: Vec<f64> = cell_rise.lvf_values.iter().map(|v| v.std_dev).collect();
+  for (got, want) in std_dev.iter().zip([0.05, 0.1, 0.15, 0.2]) {
+    assert!((got - want).abs() < 1e-12, "{std_dev:?}");
+  }
+  assert_eq!(cell_rise.lvf_values[3].mean, 4.0 * 1.01);
+  assert!(cell_rise
+    .comments
+    .contains("provenance: predicted from=INVD1BWP30P140"));
+  assert!(lib.to_string().contains("ocv_std_dev_rise_transition"));
+  Ok(())
+}
+
+#[test]
+fn observe_lvf_grid() {
+  let table = TimingTableLookUp::<DefaultCtx> {
+    index_1: vec![0.1, 0.2],
+    index_2: vec![0.01, 0.02],
+    values: vec![1.0, 2.0, 0.0, 4.0],
+    lvf_values: vec![LVFValue { mean: 1.0, std_dev: 0.1, skewness: 0.0 }; 4],
+    ..Default::default()
+  };
+  let model = Model::default();
+  let (shape, observed) = model.observe(0.0, &table).expect("same grid");
+  assert_eq!(shape, (2, 2));
+  assert_eq!(observed.iter().filter(|o| o.is_some()).count(), 3);
+  let shifted = TimingTableLookUp { lvf_index_1: vec![0.1, 0.3], ..table };
+  assert!(model.observe(0.0, &shifted).is_err());
+}
+
+#[test]
+fn predict_lib() -> anyhow::Result<()> {
+  use std::{
+    fs::File,
+    io::{BufWriter, Write},
+  };
+  let mut lib = crate::read_lib("pruned_active_lvf_0503.lib")?;
+  let report = predict(&mut lib, &PredictOptions::default());
+  for skipped in report.skipped.iter() {
+    println!("skipped {skipped}");
+  }
+  let manifest: BTreeMap<String, &Provenance> =
+    report.predicted.iter().map(|p| (p.key(), p)).collect();
+  let manifest_path = "pruned_active_lvf_0503.predicted.json";
+  serde_json::to_writer_pretty(BufWriter::new(File::create(manifest_path)?), &manifest)?;
+  let lib_path = "pruned_active_lvf_0503_predicted.lib";
+  let mut writer = BufWriter::new(File::create(lib_path)?);
+  write!(&mut writer, "{lib}")?;
+  Ok(())
+}
@@ -0,0 +1,177 @@
+//! Where the numbers of a generated table came from.
+//!
+//! Each table collected by [`crate::arcs::update_cell`] carries a one-line
+//! `provenance:` comment of `key=value` pairs, and the whole record, source file
+//! hashes included, goes to a JSON manifest keyed by `cell/pin/timing/table`.
+use crate::{flow::Fingerprint, pvt_of, Config};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{
+  collections::BTreeMap,
+  path::Path,
+  time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The characterization run behind a table
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Run {
+  pub run: String,
+  pub config: String,
+  pub lvf_type: String,
+  pub samples: usize,
+  pub pvt: Option<String>,
+}
+
+impl Run {
+  pub fn new(run: &str, config: &Config) -> Self {
+    Self {
+      run: run.to_string(),
+      config: config.Name.clone(),
+      lvf_type: config.LvfType.clone(),
+      samples: config.LVFSamplingNum,
+      pvt: pvt_of(&config.Name).map(String::from),
+    }
+  }
+}
+
+/// [`Run`] of the YAML config btdcell ran with
+pub fn read_run(run: &str, config_path: impl AsRef<Path>) -> anyhow::Result<Run> {
+  let path = config_path.as_ref();
+  let file = std::fs::File::open(path)
+    .map_err(|e| anyhow::anyhow!("read {}: {e}", path.display()))?;
+  let config: Config = serde_yaml::from_reader(file)
+    .map_err(|e| anyhow::anyhow!("parse {}: {e}", path.display()))?;
+  Ok(Run::new(run, &config))
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Provenance {
+  pub cell: String,
+  pub pin: String,
+  /// related pin and `when` of the timing group
+  pub timing: String,
+  pub table: String,
+  pub arc: String,
+  pub run: Option<Run>,
+  /// UTC, RFC 3339
+  pub collected: String,
+  /// sha256 of every file read for the table
+  pub sources: BTreeMap<String, String>,
+  /// characterized cells a predicted table was derived from, empty when measured
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub predicted_from: Vec<String>,
+}
+
+impl Provenance {
+  pub fn key(&self) -> String {
+    format!("{}/{}/{}/{}", self.cell, self.pin, self.timing, self.table)
+  }
+  /// sha256 over the sorted source hashes
+  pub fn digest(&self) -> String {
+    let mut hasher = Sha256::new();
+    for (path, sha256) in self.sources.iter() {
+      hasher.update(path.as_bytes());
+      hasher.update(sha256.as_bytes());
+    }
+    hasher.finalize().iter().map(|b| format!("{b:02x}")).collect()
+  }
+  pub fn comment(&self) -> String {
+    let mut comment = String::from("provenance:");
+    if !self.predicted_from.is_empty() {
+      comment.push_str(&format!(" predicted from={}", self.predicted_from.join(",")));
+    }
+    comment.push_str(&format!(" cell={} arc={}", self.cell, self.arc));
+    if let Some(run) = &self.run {
+      comment.push_str(&format!(
+        " run={} config={} lvf_type={} samples={}",
+        run.run, run.config, run.lvf_type, run.samples
+      ));
+      if let Some(pvt) = &run.pvt {
+        comment.push_str(&format!(" pvt={pvt}"));
+      }
+    }
+    comment.push_str(&format!(
+      " collected={} sources={} sha256={}",
+      self.collected,
+      self.sources.len(),
+      self.digest()
+    ));
+    comment
+  }
+}
+
+/// `path -> sha256` of `files`
+pub fn hash_files<'a>(
+  files: impl IntoIterator<Item = &'a Path>,
+) -> anyhow::Result<BTreeMap<String, String>> {
+  files
+    .into_iter()
+    .map(|path| Ok((path.display().to_string(), Fingerprint::of(path, None)?.sha256)))
+    .collect()
+}
+
+/// `secs` after the Unix epoch as `YYYY-MM-DDTHH:MM:SSZ`
+pub fn utc_timestamp(secs: u64) -> String {
+  let (days, rem) = ((secs / 86400) as i64, secs % 86400);
+  // civil date from days, H. Hinnant's algorithm
+  let z = days + 719_468;
+  let era = z.div_euclid(146_097);
+  let doe = z - era * 146_097;
+  let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+  let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+  let mp = (5 * doy + 2) / 153;
+  let day = doy - (153 * mp + 2) / 5 + 1;
+  let month = if mp < 10 { mp + 3 } else { mp - 9 };
+  let year = yoe + era * 400 + i64::from(month <= 2);
+  format!(
+    "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+    rem / 3600,
+    rem % 3600 / 60,
+    rem % 60
+  )
+}
+
+pub fn now() -> String {
+  let secs = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map_or(0, |d| d.as_secs());
+  utc_timestamp(secs)
+}
+
+#[test]
+fn provenance_comment() {
+  assert_eq!(utc_timestamp(0), "1970-01-01T00:00:00Z");
+  assert_eq!(utc_timestamp(951_782_400 + 3661), "2000-02-29T01:01:01Z");
+  let provenance = Provenance {
+    cell: "INVD1BWP30P140".into(),
+    pin: "ZN".into(),
+    timing: "I".into(),
+    table: "cell_rise".into(),
+    arc: "0".into(),
+    run: Some(Run {
+      run: "10k_QMC".into(),
+      config: "INV_10k_QMC_tt0p8v25c".into(),
+      lvf_type: "QmcSample".into(),
+      samples: 10000,
+      pvt: pvt_of("INV_10k_QMC_tt0p8v25c").map(String::from),
+    }),
+    collected: utc_timestamp(0),
+    sources: BTreeMap::from([("0_moments.csv".into(), "ab".into())]),
+    predicted_from: Vec::new(),
+  };
+  assert_eq!(provenance.key(), "INVD1BWP30P140/ZN/I/cell_rise");
+  assert!(provenance.comment().starts_with(
+    "provenance: cell=INVD1BWP30P140 arc=0 run=10k_QMC config=INV_10k_QMC_tt0p8v25c \
+     lvf_type=QmcSample samples=10000 pvt=tt0p8v25c collected=1970-01-01T00:00:00Z sources=1 sha256="
+  ));
+  let predicted = Provenance {
+    cell: "INVD4BWP30P140".into(),
+    run: None,
+    sources: BTreeMap::new(),
+    predicted_from: vec!["INVD1BWP30P140".into()],
+    ..provenance
+  };
+  assert!(predicted
+    .comment()
+    .starts_with("provenance: predicted from=INVD1BWP30P140 cell=INVD4BWP30P140 arc=0"));
+}
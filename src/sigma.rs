@@ -0,0 +1,107 @@
+//! Early/late `ocv_sigma_*` tables derived from the LVF moments.
+//!
+//! The early and late quantiles at `n_sigma` come from a Cornish-Fisher
+//! expansion of the mean, std dev and skewness; each is written as the sigma
+//! that puts `nominal -/+ n_sigma * sigma` on it, so the mean shift is folded in.
+use liberty_db::{
+  table::{OcvSigmaTable, SigmaType, Values},
+  timing::{LVFValue, TimingTableLookUp},
+  DefaultCtx, Library,
+};
+
+/// Standard quantile `z` corrected for `skewness` (second order, without kurtosis)
+pub fn cornish_fisher(z: f64, skewness: f64) -> f64 {
+  z + (z * z - 1.0) * skewness / 6.0
+    - (2.0 * z.powi(3) - 5.0 * z) * skewness.powi(2) / 36.0
+}
+
+/// `(early, late)` sigma of one point, never negative
+pub fn early_late(lvf: &LVFValue, nominal: f64, n_sigma: f64) -> (f64, f64) {
+  let quantile = |z: f64| lvf.mean + lvf.std_dev * cornish_fisher(z, lvf.skewness);
+  let early = (nominal - quantile(-n_sigma)) / n_sigma;
+  let late = (quantile(n_sigma) - nominal) / n_sigma;
+  (early.max(0.0), late.max(0.0))
+}
+
+fn sigma_table(
+  table: &TimingTableLookUp<DefaultCtx>,
+  sigma_type: SigmaType,
+  n_sigma: f64,
+) -> OcvSigmaTable<DefaultCtx> {
+  let inner = table
+    .lvf_values
+    .iter()
+    .zip(table.values.iter())
+    .map(|(lvf, nominal)| {
+      let (early, late) = early_late(lvf, *nominal, n_sigma);
+      if sigma_type == SigmaType::Early {
+        early
+      } else {
+        late
+      }
+    })
+    .collect();
+  let mut sigma = OcvSigmaTable::default();
+  sigma.name.clone_from(&table.name);
+  sigma.sigma_type = sigma_type;
+  sigma.index_1.clone_from(&table.index_1);
+  sigma.index_2.clone_from(&table.index_2);
+  sigma.values = Values { size1: table.size1, size2: table.size2, inner };
+  sigma
+    .comments_this_entry()
+    .or_default()
+    .push_str(&format!("Cornish-Fisher at {n_sigma} sigma"));
+  sigma
+}
+
+/// Adds early and late `ocv_sigma_*` tables next to every delay and transition
+/// table with LVF values, replacing earlier ones. Returns the number of tables added.
+pub fn insert_sigma_tables(lib: &mut Library<DefaultCtx>, n_sigma: f64) -> usize {
+  let mut count = 0;
+  for cell in lib.cell.iter_mut() {
+    for pin in cell.pin.iter_mut() {
+      for timing in pin.timing.iter_mut() {
+        for (table, sigma) in [
+          (&timing.cell_rise, &mut timing.ocv_sigma_cell_rise),
+          (&timing.cell_fall, &mut timing.ocv_sigma_cell_fall),
+          (&timing.rise_transition, &mut timing.ocv_sigma_rise_transition),
+          (&timing.fall_transition, &mut timing.ocv_sigma_fall_transition),
+        ] {
+          let Some(table) = table.as_ref().filter(|t| !t.lvf_values.is_empty()) else {
+            continue;
+          };
+          for sigma_type in [SigmaType::Early, SigmaType::Late] {
+            sigma.replace(sigma_table(table, sigma_type, n_sigma));
+            count += 1;
+          }
+        }
+      }
+    }
+  }
+  count
+}
+
+#[test]
+fn sigma_early_late() {
+  // symmetric without skewness, the mean shift moves it
+  let lvf = LVFValue { mean: 1.0, std_dev: 0.1, skewness: 0.0 };
+  let (early, late) = early_late(&lvf, 1.0, 3.0);
+  assert!((early - 0.1).abs() < 1e-12 && (late - 0.1).abs() < 1e-12);
+  let (early, late) = early_late(&LVFValue { mean: 1.03, ..lvf }, 1.0, 3.0);
+  assert!((early - 0.09).abs() < 1e-12 && (late - 0.11).abs() < 1e-12);
+  // a right-skewed distribution has a longer late tail
+  let (early, late) = early_late(&LVFValue { skewness: 0.5, ..lvf }, 1.0, 3.0);
+  assert!(late > 0.1 && early < late);
+}
+
+#[test]
+fn sigma_lib() -> anyhow::Result<()> {
+  use std::io::Write;
+  let lib_path = "pruned_active_lvf_0503.lib";
+  let mut lib = crate::read_lib(lib_path)?;
+  println!("{} sigma tables", insert_sigma_tables(&mut lib, 3.0));
+  let out_path = "pruned_active_lvf_0503_sigma.lib";
+  let mut writer = std::io::BufWriter::new(std::fs::File::create(out_path)?);
+  write!(&mut writer, "{lib}")?;
+  Ok(())
+}
@@ -0,0 +1,425 @@
+//! Outlier points of the OCV tables and their optional smoothing.
+//!
+//! Each point is compared with a plane (a line in 1-D tables) fitted over its
+//! neighbours within one step on either axis, the point itself left out; the
+//! window widens where that gives too few points, as at the corners of the table. A
+//! point whose residual is more than `threshold` robust standard deviations
+//! (1.4826 MAD of all residuals of the table) away, and more than `min_relative`
+//! of its value, is an outlier. Outliers can be replaced by that local fit or by
+//! a quadratic over the whole table fitted without them; every replaced value
+//! goes to the audit log.
+use crate::{arcs::push_comment, lint::timing_path, pocv::median};
+use liberty_db::{
+  timing::{LVFValue, TimingTableLookUp},
+  DefaultCtx, Library,
+};
+use serde::Serialize;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Component {
+  MeanShift,
+  StdDev,
+  Skewness,
+}
+
+impl Component {
+  fn get(self, lvf: &LVFValue, nominal: f64) -> f64 {
+    match self {
+      Component::MeanShift => lvf.mean - nominal,
+      Component::StdDev => lvf.std_dev,
+      Component::Skewness => lvf.skewness,
+    }
+  }
+  fn set(self, lvf: &mut LVFValue, nominal: f64, value: f64) {
+    match self {
+      Component::MeanShift => lvf.mean = nominal + value,
+      Component::StdDev => lvf.std_dev = value,
+      Component::Skewness => lvf.skewness = value,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Replace {
+  /// only report
+  #[default]
+  None,
+  /// the local plane the point was tested against
+  Local,
+  /// a quadratic surface in slew and load over the table, outliers left out
+  Polynomial,
+}
+
+#[derive(Debug, Clone)]
+pub struct SmoothOptions {
+  pub components: Vec<Component>,
+  pub threshold: f64,
+  pub min_relative: f64,
+  pub replace: Replace,
+}
+
+impl Default for SmoothOptions {
+  fn default() -> Self {
+    Self {
+      components: vec![Component::StdDev, Component::Skewness],
+      threshold: 3.5,
+      min_relative: 0.05,
+      replace: Replace::None,
+    }
+  }
+}
+
+/// An outlier point, with the value it got when replaced
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Outlier {
+  pub path: String,
+  pub component: Component,
+  /// `(index_1, index_2)` position of the point
+  pub index: (usize, usize),
+  pub old: f64,
+  pub fit: f64,
+  pub new: Option<f64>,
+}
+
+impl fmt::Display for Outlier {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let (i1, i2) = self.index;
+    write!(
+      f,
+      "{} {:?} ({i1}, {i2}): {} fit {}",
+      self.path, self.component, self.old, self.fit
+    )?;
+    if let Some(new) = self.new {
+      write!(f, " -> {new}")?;
+    }
+    Ok(())
+  }
+}
+
+/// Least-squares coefficients of `rows` against `y`, `None` when singular
+fn least_squares(rows: &[Vec<f64>], y: &[f64]) -> Option<Vec<f64>> {
+  let n = rows.first()?.len();
+  // normal equations, augmented with the right-hand side
+  let mut a = vec![vec![0.0; n + 1]; n];
+  for (row, y) in rows.iter().zip(y) {
+    for i in 0..n {
+      for j in 0..n {
+        a[i][j] += row[i] * row[j];
+      }
+      a[i][n] += row[i] * y;
+    }
+  }
+  for col in 0..n {
+    let pivot = (col..n).max_by(|i, j| a[*i][col].abs().total_cmp(&a[*j][col].abs()))?;
+    if a[pivot][col].abs() < 1e-12 {
+      return None;
+    }
+    a.swap(col, pivot);
+    for row in 0..n {
+      if row != col {
+        let factor = a[row][col] / a[col][col];
+        let pivot_row = a[col].clone();
+        for (value, p) in a[row].iter_mut().zip(pivot_row).skip(col) {
+          *value -= factor * p;
+        }
+      }
+    }
+  }
+  Some((0..n).map(|i| a[i][n] / a[i][i]).collect())
+}
+
+type Basis = fn(f64, f64) -> Vec<f64>;
+
+fn plane(x: f64, y: f64) -> Vec<f64> {
+  vec![1.0, x, y]
+}
+
+fn quadratic(x: f64, y: f64) -> Vec<f64> {
+  vec![1.0, x, y, x * y, x * x, y * y]
+}
+
+// a single-row or single-column table has the other unit axis all 0, so `x + y`
+// runs along the one that varies
+fn line(x: f64, y: f64) -> Vec<f64> {
+  vec![1.0, x + y]
+}
+
+fn parabola(x: f64, y: f64) -> Vec<f64> {
+  vec![1.0, x + y, (x + y) * (x + y)]
+}
+
+/// `index` scaled to `[0, 1]`, positions when it is missing; `None` when empty
+fn unit_axis(index: &[f64], size: usize) -> Option<Vec<f64>> {
+  let axis: Vec<f64> = if index.len() == size {
+    index.to_vec()
+  } else {
+    (0..size).map(|i| i as f64).collect()
+  };
+  let (lo, hi) = (*axis.first()?, *axis.last()?);
+  Some(
+    axis
+      .iter()
+      .map(|v| if hi > lo { (v - lo) / (hi - lo) } else { 0.0 })
+      .collect(),
+  )
+}
+
+/// Value at `(x, y)` of `basis` fitted to `points`, which must outnumber the
+/// coefficients by `spare`
+fn fit_at(
+  basis: Basis,
+  spare: usize,
+  points: &[(f64, f64, f64)],
+  x: f64,
+  y: f64,
+) -> Option<f64> {
+  let rows: Vec<Vec<f64>> = points.iter().map(|(x, y, _)| basis(*x, *y)).collect();
+  if rows.len() < rows.first()?.len() + spare {
+    return None;
+  }
+  let values: Vec<f64> = points.iter().map(|(_, _, v)| *v).collect();
+  let coefficients = least_squares(&rows, &values)?;
+  Some(basis(x, y).iter().zip(coefficients).map(|(b, c)| b * c).sum())
+}
+
+/// Outliers of one component of `grid`, a `size1 x size2` row-major table over
+/// the unit axes `x` and `y`, as `(position, local fit)`. They are taken largest
+/// residual first, each left out of the fits of the points tested after it. In a
+/// 1-D table the local fit is the line through the two nearest neighbours.
+fn outliers(
+  grid: &[f64],
+  x: &[f64],
+  y: &[f64],
+  options: &SmoothOptions,
+) -> Vec<(usize, f64)> {
+  let (size1, size2) = (x.len(), y.len());
+  let (basis, spare): (Basis, usize) =
+    if size1 == 1 || size2 == 1 { (line, 0) } else { (plane, 1) };
+  let local = |i: usize, left_out: &[(usize, f64)]| {
+    let (i1, i2) = (i / size2, i % size2);
+    (1..size1.max(size2)).find_map(|steps| {
+      let mut neighbours = Vec::new();
+      for (j1, x1) in x
+        .iter()
+        .enumerate()
+        .take(i1 + steps + 1)
+        .skip(i1.saturating_sub(steps))
+      {
+        for (j2, y2) in y
+          .iter()
+          .enumerate()
+          .take(i2 + steps + 1)
+          .skip(i2.saturating_sub(steps))
+        {
+          let j = j1 * size2 + j2;
+          if j != i && left_out.iter().all(|(k, _)| *k != j) {
+            neighbours.push((*x1, *y2, grid[j]));
+          }
+        }
+      }
+      fit_at(basis, spare, &neighbours, x[i1], y[i2])
+    })
+  };
+  let residuals: Vec<f64> = (0..grid.len())
+    .filter_map(|i| local(i, &[]).map(|fit| grid[i] - fit))
+    .collect();
+  let Some(center) = median(residuals.clone()) else {
+    return Vec::new();
+  };
+  let deviations = residuals.iter().map(|r| (r - center).abs()).collect();
+  let scale = 1.4826 * median(deviations).unwrap_or_default();
+  let mut out: Vec<(usize, f64)> = Vec::new();
+  loop {
+    let worst = (0..grid.len())
+      .filter(|i| out.iter().all(|(j, _)| j != i))
+      .filter_map(|i| {
+        let fit = local(i, &out)?;
+        let residual = (grid[i] - fit - center).abs();
+        let outlier = residual > options.threshold * scale
+          && residual > options.min_relative * grid[i].abs();
+        outlier.then_some((i, fit, residual))
+      })
+      .max_by(|a, b| a.2.total_cmp(&b.2));
+    match worst {
+      Some((i, fit, _)) => out.push((i, fit)),
+      None => break,
+    }
+  }
+  out.sort_by_key(|(i, _)| *i);
+  out
+}
+
+/// Outliers of the LVF values of `table`, replaced as `options` asks
+pub fn smooth_table(
+  path: &str,
+  table: &mut TimingTableLookUp<DefaultCtx>,
+  options: &SmoothOptions,
+) -> Vec<Outlier> {
+  let size2 = table.index_2.len().max(1);
+  let size1 = table.values.len() / size2;
+  if table.lvf_values.len() != table.values.len() || size1 * size2 != table.values.len() {
+    return Vec::new();
+  }
+  let (Some(x), Some(y)) =
+    (unit_axis(&table.index_1, size1), unit_axis(&table.index_2, size2))
+  else {
+    return Vec::new();
+  };
+  let surface = if size1 == 1 || size2 == 1 { parabola } else { quadratic };
+  let mut out = Vec::new();
+  for component in options.components.iter().copied() {
+    let grid: Vec<f64> = (0..table.values.len())
+      .map(|i| component.get(&table.lvf_values[i], table.values[i]))
+      .collect();
+    let found = outliers(&grid, &x, &y, options);
+    let kept: Vec<(f64, f64, f64)> = (0..grid.len())
+      .filter(|i| found.iter().all(|(j, _)| j != i))
+      .map(|i| (x[i / size2], y[i % size2], grid[i]))
+      .collect();
+    for (i, fit) in found {
+      let index = (i / size2, i % size2);
+      let new = match options.replace {
+        Replace::None => None,
+        Replace::Local => Some(fit),
+        Replace::Polynomial => {
+          fit_at(surface, 1, &kept, x[index.0], y[index.1]).or(Some(fit))
+        }
+      };
+      if let Some(new) = new {
+        component.set(&mut table.lvf_values[i], table.values[i], new);
+      }
+      out.push(Outlier {
+        path: path.to_string(),
+        component,
+        index,
+        old: grid[i],
+        fit,
+        new,
+      });
+    }
+  }
+  out
+}
+
+/// [`smooth_table`] over every table of `lib` with LVF values; replaced values
+/// also get a comment on their table
+pub fn smooth(lib: &mut Library<DefaultCtx>, options: &SmoothOptions) -> Vec<Outlier> {
+  let mut out = Vec::new();
+  for cell in lib.cell.iter_mut() {
+    let cell_name = cell.name.to_string();
+    for pin in cell.pin.iter_mut() {
+      let pin_name = pin.name.to_string();
+      for timing in pin.timing.iter_mut() {
+        let path = timing_path(&cell_name, &pin_name, timing);
+        for (name, table) in [
+          ("cell_rise", &mut timing.cell_rise),
+          ("cell_fall", &mut timing.cell_fall),
+          ("rise_transition", &mut timing.rise_transition),
+          ("fall_transition", &mut timing.fall_transition),
+        ] {
+          let Some(table) = table.as_mut() else {
+            continue;
+          };
+          let outliers = smooth_table(&format!("{path}/{name}"), table, options);
+          let replaced = outliers.iter().filter(|o| o.new.is_some()).count();
+          if replaced > 0 {
+            push_comment(
+              &mut table.comments,
+              &format!("{replaced} outlier values smoothed"),
+            );
+          }
+          out.extend(outliers);
+        }
+      }
+    }
+  }
+  out
+}
+
+#[test]
+fn smooth_outlier() {
+  let index_1 = vec![0.1, 0.2, 0.4, 0.8];
+  let index_2 = vec![1.0, 2.0, 4.0, 8.0];
+  let values: Vec<f64> = (0..16)
+    .map(|i| 1.0 + 2.0 * index_1[i / 4] + 0.5 * index_2[i % 4])
+    .collect();
+  let lvf_values: Vec<LVFValue> = values
+    .iter()
+    .map(|v| LVFValue { mean: *v, std_dev: 0.1 * v, skewness: 0.2 })
+    .collect();
+  let mut table = TimingTableLookUp::<DefaultCtx> {
+    index_1,
+    index_2,
+    size1: 4,
+    size2: 4,
+    values: values.clone(),
+    lvf_values,
+    ..Default::default()
+  };
+  let options = SmoothOptions::default();
+  assert_eq!(smooth_table("A", &mut table.clone(), &options), []);
+  table.lvf_values[5].std_dev *= 3.0;
+  let found = smooth_table("A", &mut table.clone(), &options);
+  assert_eq!(found.len(), 1);
+  assert_eq!(
+    (found[0].component, found[0].index, found[0].new),
+    (Component::StdDev, (1, 1), None)
+  );
+  let options = SmoothOptions { replace: Replace::Polynomial, ..options };
+  let found = smooth_table("A", &mut table, &options);
+  let new = found[0].new.expect("replaced");
+  assert!((new - 0.1 * values[5]).abs() < 1e-9, "{new}");
+  assert_eq!(table.lvf_values[5].std_dev, new);
+  table.lvf_values[5].std_dev = 0.1 * values[5];
+  table.lvf_values[15].skewness = 0.5;
+  let found = smooth_table("A", &mut table, &options);
+  assert_eq!(found.len(), 1);
+  assert_eq!((found[0].component, found[0].index), (Component::Skewness, (3, 3)));
+}
+
+#[test]
+fn smooth_1d() {
+  let index_1 = vec![0.1, 0.2, 0.4, 0.8, 1.6, 3.2, 6.4, 12.8];
+  let values: Vec<f64> = index_1.iter().map(|x| 1.0 + 2.0 * x).collect();
+  let lvf_values: Vec<LVFValue> = values
+    .iter()
+    .map(|v| LVFValue { mean: *v, std_dev: 0.1 * v, skewness: 0.2 })
+    .collect();
+  let mut table = TimingTableLookUp::<DefaultCtx> {
+    index_1,
+    size1: 8,
+    size2: 1,
+    values: values.clone(),
+    lvf_values,
+    ..Default::default()
+  };
+  let options = SmoothOptions { replace: Replace::Polynomial, ..Default::default() };
+  assert_eq!(smooth_table("A", &mut table.clone(), &options), []);
+  table.lvf_values[3].std_dev *= 3.0;
+  let found = smooth_table("A", &mut table, &options);
+  assert_eq!(found.len(), 1);
+  assert_eq!((found[0].component, found[0].index), (Component::StdDev, (3, 0)));
+  let new = found[0].new.expect("replaced");
+  assert!((new - 0.1 * values[3]).abs() < 1e-9, "{new}");
+  let mut empty = TimingTableLookUp::<DefaultCtx>::default();
+  assert_eq!(smooth_table("A", &mut empty, &options), []);
+}
+
+#[test]
+fn smooth_lib() -> anyhow::Result<()> {
+  use std::io::Write;
+  let mut lib = crate::read_lib("pruned_active_lvf_0503.lib")?;
+  let options = SmoothOptions { replace: Replace::Polynomial, ..Default::default() };
+  let audit = smooth(&mut lib, &options);
+  for outlier in audit.iter() {
+    println!("{outlier}");
+  }
+  let audit_path = "pruned_active_lvf_0503.smooth.json";
+  serde_json::to_writer_pretty(std::fs::File::create(audit_path)?, &audit)?;
+  let out_path = "pruned_active_lvf_0503_smooth.lib";
+  let mut writer = std::io::BufWriter::new(std::fs::File::create(out_path)?);
+  write!(&mut writer, "{lib}")?;
+  Ok(())
+}
@@ -0,0 +1,124 @@
+//! Moments of a table point from its raw Monte Carlo samples.
+//!
+//! `<index>_samples.csv` holds one sample per line with a delay and a
+//! transition column, located through the header like the moments CSV
+//! (`delay(ps)`, `tran`, `slew[ns]`, ...); other columns are ignored. Samples
+//! that are not finite are dropped, so bad runs can be blanked out and the
+//! statistics recomputed without simulating again.
+use crate::moments::{
+  cells, is_nan_marker, split_unit, unit_scale, Moments, PointMoments,
+};
+use anyhow::{bail, Context as _};
+use std::{fs, path::Path, str::FromStr};
+
+/// Mean, std dev and skewness of `samples` with the bias-corrected estimators,
+/// kurtosis (not excess) once there are four samples.
+/// Std dev needs two samples and skewness three, they are NaN below that.
+pub fn moments_of(samples: &[f64]) -> Moments {
+  let n = samples.len() as f64;
+  let mean = samples.iter().sum::<f64>() / n;
+  let central = |k: i32| samples.iter().map(|x| (x - mean).powi(k)).sum::<f64>() / n;
+  let (m2, m3, m4) = (central(2), central(3), central(4));
+  let std_dev = if n < 2.0 { f64::NAN } else { (m2 * n / (n - 1.0)).sqrt() };
+  let skewness = if n < 3.0 {
+    f64::NAN
+  } else if m2 == 0.0 {
+    0.0
+  } else {
+    (n * (n - 1.0)).sqrt() / (n - 2.0) * m3 / m2.powf(1.5)
+  };
+  let kurtosis = (n >= 4.0 && m2 != 0.0).then(|| {
+    let g2 = m4 / (m2 * m2) - 3.0;
+    ((n + 1.0) * g2 + 6.0) * (n - 1.0) / ((n - 2.0) * (n - 3.0)) + 3.0
+  });
+  Moments { mean, std_dev, skewness, kurtosis }
+}
+
+/// Delay and transition samples in seconds, non-finite ones dropped
+pub fn parse_samples(file: &Path, text: &str) -> anyhow::Result<(Vec<f64>, Vec<f64>)> {
+  let mut lines = text
+    .split('\n')
+    .enumerate()
+    .map(|(n, l)| (n + 1, l))
+    .filter(|(_, l)| !cells(l, 0).is_empty());
+  let (_, header) = lines
+    .next()
+    .with_context(|| format!("{}: missing header line", file.display()))?;
+  let (mut delay, mut transition) = (None, None);
+  for (idx, name) in cells(header, 0).iter().enumerate() {
+    let (bare, unit) = split_unit(name);
+    let bare = bare.to_ascii_lowercase();
+    let column = if bare.contains("delay") || bare.contains("cell") {
+      &mut delay
+    } else if bare.contains("tran") || bare.contains("slew") {
+      &mut transition
+    } else {
+      continue;
+    };
+    if column.is_some() {
+      bail!("{}: column {}: duplicated column {name:?}", file.display(), idx + 1);
+    }
+    let scale = match unit {
+      None => 1.0,
+      Some(unit) => unit_scale(unit).with_context(|| {
+        format!("{}: column {}: unknown time unit {unit:?}", file.display(), idx + 1)
+      })?,
+    };
+    *column = Some((idx, scale));
+  }
+  let (Some(delay), Some(transition)) = (delay, transition) else {
+    bail!("{}: needs a delay and a transition column", file.display());
+  };
+  let (mut delays, mut transitions) = (Vec::new(), Vec::new());
+  for (line, data) in lines {
+    let values = cells(data, 0);
+    for ((idx, scale), samples) in [(delay, &mut delays), (transition, &mut transitions)]
+    {
+      let value = match values.get(idx) {
+        None => f64::NAN,
+        Some(s) if is_nan_marker(s) => f64::NAN,
+        Some(s) => f64::from_str(s).map_err(|_| {
+          anyhow::anyhow!(
+            "{}:{line}: column {}: invalid number {s:?}",
+            file.display(),
+            idx + 1
+          )
+        })?,
+      };
+      if value.is_finite() {
+        samples.push(value * scale);
+      }
+    }
+  }
+  Ok((delays, transitions))
+}
+
+pub fn read_samples(file: &Path) -> anyhow::Result<PointMoments> {
+  let text =
+    fs::read_to_string(file).with_context(|| format!("read {}", file.display()))?;
+  let (delay, transition) = parse_samples(file, &text)?;
+  Ok(PointMoments {
+    delay: moments_of(&delay),
+    transition: moments_of(&transition),
+  })
+}
+
+#[test]
+fn sample_moments() -> anyhow::Result<()> {
+  let samples = [1.0, 2.0, 3.0, 4.0, 10.0];
+  let moments = moments_of(&samples);
+  assert_eq!(moments.mean, 4.0);
+  // reference values of Excel's STDEV.S, SKEW and KURT + 3
+  assert!((moments.std_dev - 3.535_533_905_932_737_6).abs() < 1e-12);
+  assert!((moments.skewness - 1.697_056_274_847_714).abs() < 1e-12);
+  assert!((moments.kurtosis.unwrap() - 3.0 - 3.152).abs() < 1e-12);
+  assert!(moments_of(&[1.0, 2.0]).skewness.is_nan());
+
+  let file = Path::new("0_samples.csv");
+  let text = "sample,delay(ps),slew(ps)\r\n0,1,10\r\n1,nan,20\r\n2,3,30\r\n";
+  let (delay, transition) = parse_samples(file, text)?;
+  assert_eq!(delay, [1e-12, 3e-12]);
+  assert_eq!(transition, [10e-12, 20e-12, 30e-12]);
+  assert!(parse_samples(file, "sample,delay\n0,1\n").is_err());
+  Ok(())
+}
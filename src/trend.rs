@@ -0,0 +1,323 @@
+//! Trends of the LVF data across corners and across drive strengths.
+//!
+//! Every table is reduced to its mean std dev and mean sigma/nominal ratio. Per
+//! cell, the std dev should not grow with the supply voltage and should only grow
+//! or only shrink with temperature; per `CELL_GROUP` family and corner, the ratio
+//! should change in one direction and by bounded steps from drive to drive.
+use crate::{corner::Corner, drive_of, family_of, lint::timing_path, PVT};
+use liberty_db::{DefaultCtx, Library};
+use serde::Serialize;
+use std::{collections::BTreeMap, fmt, io::Write};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrendOptions {
+  /// relative change below which two neighbours count as equal
+  pub slack: f64,
+  /// largest factor between the ratios of neighbouring drives
+  pub max_drive_jump: f64,
+}
+
+impl Default for TrendOptions {
+  fn default() -> Self {
+    Self { slack: 0.05, max_drive_jump: 2.0 }
+  }
+}
+
+/// One table of one cell at one corner
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Sample {
+  pub corner: Corner,
+  pub cell: String,
+  pub drive: f64,
+  /// `family/pin/timing(...)/table`, the same for every drive of the family
+  pub arc: String,
+  pub std_dev: f64,
+  /// mean of std dev / nominal over the points with a positive nominal value
+  pub ratio: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Trend {
+  /// std dev grows with the supply voltage
+  Voltage,
+  /// std dev turns along temperature
+  Temperature,
+  /// sigma/nominal turns along drive strength
+  Drive,
+  /// sigma/nominal of neighbouring drives differs by more than allowed
+  DriveJump,
+}
+
+/// A series breaking its trend, with all of its values
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TrendViolation {
+  pub trend: Trend,
+  pub arc: String,
+  /// what is held fixed along the series
+  pub key: String,
+  pub labels: Vec<String>,
+  pub values: Vec<f64>,
+  /// position of the offending value
+  pub position: usize,
+}
+
+impl fmt::Display for TrendViolation {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{:?} {} [{}]:", self.trend, self.arc, self.key)?;
+    for (i, (label, value)) in self.labels.iter().zip(self.values.iter()).enumerate() {
+      let mark = if i == self.position { "*" } else { "" };
+      write!(f, " {mark}{label}={value:.4e}")?;
+    }
+    Ok(())
+  }
+}
+
+/// The per-table samples of `lib`, collected at `corner`
+pub fn samples(corner: Corner, lib: &Library<DefaultCtx>) -> Vec<Sample> {
+  let mut out = Vec::new();
+  for cell in lib.cell.iter() {
+    let (Some(family), Some(drive)) = (family_of(&cell.name), drive_of(&cell.name))
+    else {
+      continue;
+    };
+    for pin in cell.pin.iter() {
+      for timing in pin.timing.iter() {
+        let path = timing_path(family, &pin.name.to_string(), timing);
+        for (name, table) in [
+          ("cell_rise", &timing.cell_rise),
+          ("cell_fall", &timing.cell_fall),
+          ("rise_transition", &timing.rise_transition),
+          ("fall_transition", &timing.fall_transition),
+        ] {
+          let Some(table) = table else {
+            continue;
+          };
+          if table.lvf_values.is_empty() || table.lvf_values.len() != table.values.len() {
+            continue;
+          }
+          let n = table.lvf_values.len() as f64;
+          let std_dev = table.lvf_values.iter().map(|lvf| lvf.std_dev).sum::<f64>() / n;
+          let ratios: Vec<f64> = table
+            .lvf_values
+            .iter()
+            .zip(table.values.iter())
+            .filter(|(_, nominal)| **nominal > 0.0)
+            .map(|(lvf, nominal)| lvf.std_dev / nominal)
+            .collect();
+          if ratios.is_empty() {
+            continue;
+          }
+          let ratio = ratios.iter().sum::<f64>() / ratios.len() as f64;
+          out.push(Sample {
+            corner,
+            cell: cell.name.to_string(),
+            drive,
+            arc: format!("{path}/{name}"),
+            std_dev,
+            ratio,
+          });
+        }
+      }
+    }
+  }
+  out
+}
+
+/// Position where `values` turn direction, ignoring steps within `slack`
+fn turn(values: &[f64], slack: f64) -> Option<usize> {
+  let mut direction = 0.0;
+  let mut last = *values.first()?;
+  for (i, value) in values.iter().enumerate().skip(1) {
+    let step = value - last;
+    if step.abs() <= slack * last.abs() {
+      continue;
+    }
+    if direction * step < 0.0 {
+      return Some(i);
+    }
+    direction = step.signum();
+    last = *value;
+  }
+  None
+}
+
+type Series = BTreeMap<(String, String), Vec<(f64, String, f64)>>;
+
+/// `samples` grouped by arc and by the key of `point`, each group sorted along
+/// its axis
+fn series(
+  samples: &[Sample],
+  point: impl Fn(&Sample) -> Option<(String, f64, String, f64)>,
+) -> Series {
+  let mut out = Series::new();
+  for sample in samples {
+    if let Some((key, axis, label, value)) = point(sample) {
+      out
+        .entry((sample.arc.clone(), key))
+        .or_default()
+        .push((axis, label, value));
+    }
+  }
+  for points in out.values_mut() {
+    points.sort_by(|a, b| a.0.total_cmp(&b.0));
+  }
+  out
+}
+
+fn violation(
+  trend: Trend,
+  (arc, key): (String, String),
+  points: &[(f64, String, f64)],
+  position: usize,
+) -> TrendViolation {
+  TrendViolation {
+    trend,
+    arc,
+    key,
+    labels: points.iter().map(|(_, label, _)| label.clone()).collect(),
+    values: points.iter().map(|(_, _, value)| *value).collect(),
+    position,
+  }
+}
+
+/// Series of `samples` that break the expected trends, sorted by trend and arc
+pub fn trends(samples: &[Sample], options: &TrendOptions) -> Vec<TrendViolation> {
+  let pvt = |sample: &Sample| {
+    PVT.iter().find(|(name, _, _, _)| *name == sample.corner.pvt).map(
+      |(_, model, v, t)| {
+        let process = model.split('_').next().unwrap_or(model);
+        (process, *v, *t)
+      },
+    )
+  };
+  let mut out = Vec::new();
+  let by_voltage = series(samples, |s| {
+    let (process, v, t) = pvt(s)?;
+    let key = format!("{} {} {process} {t}C", s.cell, s.corner.run);
+    Some((key, v.into(), s.corner.pvt.to_string(), s.std_dev))
+  });
+  for (key, points) in by_voltage {
+    let grows =
+      (1..points.len()).find(|i| points[*i].2 > (1.0 + options.slack) * points[i - 1].2);
+    if let Some(position) = grows {
+      out.push(violation(Trend::Voltage, key, &points, position));
+    }
+  }
+  let by_temperature = series(samples, |s| {
+    let (process, v, t) = pvt(s)?;
+    let key = format!("{} {} {process} {v}V", s.cell, s.corner.run);
+    Some((key, t.into(), s.corner.pvt.to_string(), s.std_dev))
+  });
+  for (key, points) in by_temperature {
+    let values: Vec<f64> = points.iter().map(|p| p.2).collect();
+    if let Some(position) = turn(&values, options.slack) {
+      out.push(violation(Trend::Temperature, key, &points, position));
+    }
+  }
+  let by_drive =
+    series(samples, |s| Some((s.corner.to_string(), s.drive, s.cell.clone(), s.ratio)));
+  for (key, points) in by_drive {
+    let values: Vec<f64> = points.iter().map(|p| p.2).collect();
+    if let Some(position) = turn(&values, options.slack) {
+      out.push(violation(Trend::Drive, key.clone(), &points, position));
+    }
+    let jump = (1..values.len()).find(|i| {
+      let (a, b) = (values[i - 1], values[*i]);
+      a > 0.0 && b > 0.0 && a.max(b) / a.min(b) > options.max_drive_jump
+    });
+    if let Some(position) = jump {
+      out.push(violation(Trend::DriveJump, key, &points, position));
+    }
+  }
+  out.sort_by(|a, b| (a.trend, &a.arc, &a.key).cmp(&(b.trend, &b.arc, &b.key)));
+  out
+}
+
+pub fn write_csv(
+  writer: &mut impl Write,
+  violations: &[TrendViolation],
+) -> std::io::Result<()> {
+  writeln!(writer, "trend,arc,key,position,offending,labels,values")?;
+  for v in violations {
+    let values: Vec<String> = v.values.iter().map(|x| format!("{x:e}")).collect();
+    writeln!(
+      writer,
+      "{:?},\"{}\",\"{}\",{},{},\"{}\",\"{}\"",
+      v.trend,
+      v.arc,
+      v.key,
+      v.position,
+      v.labels[v.position],
+      v.labels.join(" "),
+      values.join(" ")
+    )?;
+  }
+  Ok(())
+}
+
+#[test]
+fn trend_checks() {
+  let sample =
+    |pvt: &'static str, cell: &str, drive: f64, std_dev: f64, ratio: f64| Sample {
+      corner: Corner { pvt, ..Default::default() },
+      cell: cell.to_string(),
+      drive,
+      arc: "INV/ZN/timing(I)/cell_rise".to_string(),
+      std_dev,
+      ratio,
+    };
+  assert_eq!(crate::drive_of("INVD0P7BWP30P140"), Some(0.7));
+  assert_eq!(crate::drive_of("ND2D16BWP30P140"), Some(16.0));
+  assert_eq!(turn(&[3.0, 2.0, 2.02, 1.0], 0.05), None);
+  assert_eq!(turn(&[3.0, 2.0, 2.5], 0.05), Some(2));
+  let samples = [
+    sample("tt0p8v25c", "INVD1BWP30P140", 1.0, 0.010, 0.10),
+    sample("tt0p9v25c", "INVD1BWP30P140", 1.0, 0.008, 0.08),
+    sample("ssg0p72v0c", "INVD1BWP30P140", 1.0, 0.020, 0.12),
+    sample("ssg0p81v0c", "INVD1BWP30P140", 1.0, 0.025, 0.13),
+    sample("tt0p8v25c", "INVD2BWP30P140", 2.0, 0.007, 0.07),
+    sample("tt0p8v25c", "INVD4BWP30P140", 4.0, 0.010, 0.09),
+    sample("tt0p8v25c", "INVD8BWP30P140", 8.0, 0.002, 0.02),
+  ];
+  let found = trends(&samples, &TrendOptions::default());
+  let found: Vec<(Trend, &str, usize)> =
+    found.iter().map(|v| (v.trend, v.key.as_str(), v.position)).collect();
+  assert_eq!(
+    found,
+    [
+      (Trend::Voltage, "INVD1BWP30P140 10k_QMC SSGlobalCorner 0C", 1),
+      (Trend::Drive, "10k_QMC_tt0p8v25c", 2),
+      (Trend::DriveJump, "10k_QMC_tt0p8v25c", 3),
+    ]
+  );
+  let mut out = Vec::new();
+  write_csv(&mut out, &trends(&samples[..4], &TrendOptions::default())).unwrap();
+  assert_eq!(
+    String::from_utf8(out).unwrap().lines().nth(1),
+    Some("Voltage,\"INV/ZN/timing(I)/cell_rise\",\"INVD1BWP30P140 10k_QMC SSGlobalCorner 0C\",1,ssg0p81v0c,\"ssg0p72v0c ssg0p81v0c\",\"2e-2 2.5e-2\"")
+  );
+}
+
+#[test]
+fn trend_report() -> anyhow::Result<()> {
+  use std::{fs::File, io::BufWriter, path::Path};
+  let corners: Vec<(Corner, String)> = Corner::all()
+    .map(|corner| (corner, format!("pruned_active_lvf_{corner}.lib")))
+    .filter(|(_, path)| Path::new(path).exists())
+    .collect();
+  let paths: Vec<&String> = corners.iter().map(|(_, path)| path).collect();
+  let libs = crate::parallel::read_libs(&paths)?;
+  let samples: Vec<Sample> = corners
+    .iter()
+    .zip(libs.iter())
+    .flat_map(|((corner, _), lib)| samples(*corner, lib))
+    .collect();
+  let violations = trends(&samples, &TrendOptions::default());
+  for violation in violations.iter() {
+    println!("{violation}");
+  }
+  let report_path = "pruned_active_lvf.trend.csv";
+  write_csv(&mut BufWriter::new(File::create(report_path)?), &violations)?;
+  Ok(())
+}